@@ -0,0 +1,234 @@
+use crate::flow::to_flow;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use yaml_rust::yaml::Hash;
+use yaml_rust::{Yaml, YamlLoader};
+
+/// Names of the project configuration, searched in this order within each directory
+pub const CONFIG_NAMES: [&str; 2] = [".curt.yaml", "curt.toml"];
+
+/// Project settings of curt-extract, read from yaml like:
+///
+/// ```yaml
+/// indicators:
+///   brackets: [ID, REF]
+///   tags: ["@", "#"]
+/// include: ["*.adoc", "*.rs"]
+/// exclude: [target]
+/// format: yaml
+/// links: [implements, tests, verifies]
+/// lint:
+///   unique: [ID]
+///   schema: schema.yaml
+/// ```
+///
+/// Or from the same keys in toml. Settings which are not given stay empty,
+/// so the defaults of the command line apply.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Config {
+    pub brackets: Vec<String>,
+    pub closures: Vec<String>,
+    pub crickets: Vec<String>,
+    pub rounds: Vec<String>,
+    pub tags: Vec<String>,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub format: Option<String>,
+    /// Attributes of a declaration linking it to other nodes, see `TraceConfig::links`
+    pub links: Option<Vec<String>>,
+    /// Identifiers whose names may only be declared once
+    pub unique: Option<Vec<String>>,
+    /// Path of the schema file, relative to the configuration once it is loaded
+    pub schema: Option<String>,
+}
+
+/// Checks that `yaml` is a mapping with no other keys than `keys`
+fn check_keys<'a>(yaml: &'a Yaml, keys: &[&str], of: &str) -> Result<Option<&'a Hash>, String> {
+    let hash = match yaml {
+        Yaml::BadValue | Yaml::Null => return Ok(None),
+        Yaml::Hash(hash) => hash,
+        _ => return Err(format!("{} is no mapping", of)),
+    };
+    for key in hash.keys() {
+        if !key.as_str().is_some_and(|key| keys.contains(&key)) {
+            return Err(format!("unknown key `{}` in {}", to_flow(key), of));
+        }
+    }
+    Ok(Some(hash))
+}
+
+/// Reads a list of words, given as list or as whitespace separated string
+fn parse_words(yaml: &Yaml, key: &str) -> Result<Option<Vec<String>>, String> {
+    match yaml {
+        Yaml::BadValue | Yaml::Null => Ok(None),
+        Yaml::String(words) => Ok(Some(
+            words
+                .split_whitespace()
+                .map(|word| word.to_string())
+                .collect(),
+        )),
+        Yaml::Array(words) => words
+            .iter()
+            .map(|word| match word {
+                Yaml::String(word) => Ok(word.clone()),
+                other => Err(format!("`{}` of `{}` is no string", to_flow(other), key)),
+            })
+            .collect::<Result<Vec<String>, String>>()
+            .map(Some),
+        other => Err(format!("`{}: {}` is no list", key, to_flow(other))),
+    }
+}
+
+fn parse_string(yaml: &Yaml, key: &str) -> Result<Option<String>, String> {
+    match yaml {
+        Yaml::BadValue | Yaml::Null => Ok(None),
+        Yaml::String(value) => Ok(Some(value.clone())),
+        other => Err(format!("`{}: {}` is no string", key, to_flow(other))),
+    }
+}
+
+/// Turns toml into the equal yaml, so both are read the same way
+fn toml_to_yaml(value: &toml::Value) -> Yaml {
+    match value {
+        toml::Value::String(value) => Yaml::String(value.clone()),
+        toml::Value::Integer(value) => Yaml::Integer(*value),
+        toml::Value::Float(value) => Yaml::Real(value.to_string()),
+        toml::Value::Boolean(value) => Yaml::Boolean(*value),
+        toml::Value::Datetime(value) => Yaml::String(value.to_string()),
+        toml::Value::Array(values) => Yaml::Array(values.iter().map(toml_to_yaml).collect()),
+        toml::Value::Table(table) => Yaml::Hash(
+            table
+                .iter()
+                .map(|(key, value)| (Yaml::String(key.clone()), toml_to_yaml(value)))
+                .collect(),
+        ),
+    }
+}
+
+impl FromStr for Config {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Config, String> {
+        let docs = YamlLoader::load_from_str(s).map_err(|error| error.to_string())?;
+        match docs.first() {
+            Some(doc) => Config::from_yaml(doc),
+            None => Ok(Config::default()),
+        }
+    }
+}
+
+impl Config {
+    /// Reads the configuration from toml, with the same keys as in yaml
+    pub fn from_toml(s: &str) -> Result<Config, String> {
+        let value: toml::Value = s
+            .parse()
+            .map_err(|error: toml::de::Error| error.to_string())?;
+        Config::from_yaml(&toml_to_yaml(&value))
+    }
+
+    fn from_yaml(doc: &Yaml) -> Result<Config, String> {
+        let keys = [
+            "indicators",
+            "include",
+            "exclude",
+            "format",
+            "links",
+            "lint",
+        ];
+        check_keys(doc, &keys, "the configuration")?;
+        let ranges = ["brackets", "closures", "crickets", "rounds", "tags"];
+        let indicators = &doc["indicators"];
+        check_keys(indicators, &ranges, "`indicators`")?;
+        let lint = &doc["lint"];
+        check_keys(lint, &["unique", "schema"], "`lint`")?;
+        let words = |yaml: &Yaml, key: &str| -> Result<Vec<String>, String> {
+            Ok(parse_words(yaml, key)?.unwrap_or_default())
+        };
+        Ok(Config {
+            brackets: words(&indicators["brackets"], "brackets")?,
+            closures: words(&indicators["closures"], "closures")?,
+            crickets: words(&indicators["crickets"], "crickets")?,
+            rounds: words(&indicators["rounds"], "rounds")?,
+            tags: words(&indicators["tags"], "tags")?,
+            include: words(&doc["include"], "include")?,
+            exclude: words(&doc["exclude"], "exclude")?,
+            format: parse_string(&doc["format"], "format")?,
+            links: parse_words(&doc["links"], "links")?,
+            unique: parse_words(&lint["unique"], "unique")?,
+            schema: parse_string(&lint["schema"], "schema")?,
+        })
+    }
+
+    /// Reads the configuration at `path`, as toml if it ends with `.toml` else as yaml
+    ///
+    /// The schema path is taken relative to the directory of the configuration.
+    pub fn load(path: &Path) -> Result<Config, String> {
+        let text = fs::read_to_string(path).map_err(|error| error.to_string())?;
+        let mut config = match path.extension() {
+            Some(extension) if extension == "toml" => Config::from_toml(&text)?,
+            _ => text.parse()?,
+        };
+        if let (Some(schema), Some(directory)) = (&config.schema, path.parent()) {
+            config.schema = Some(directory.join(schema).to_string_lossy().into_owned());
+        }
+        Ok(config)
+    }
+
+    /// Finds the nearest configuration in `directory` or one of its parents
+    pub fn find(directory: &Path) -> Option<PathBuf> {
+        directory.ancestors().find_map(|directory| {
+            CONFIG_NAMES
+                .iter()
+                .map(|name| directory.join(name))
+                .find(|path| path.is_file())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Config;
+    use crate::test_support::temp_directory;
+
+    #[test]
+    fn test_config_parse() {
+        let yaml = "indicators:\n  brackets: [ID, REF]\n  tags: \"@ #\"\ninclude: [\"*.adoc\"]\nformat: yaml\nlinks: [implements]\nlint:\n  unique: ID\n";
+        let toml = "include = [\"*.adoc\"]\nformat = \"yaml\"\nlinks = [\"implements\"]\n[indicators]\nbrackets = [\"ID\", \"REF\"]\ntags = \"@ #\"\n[lint]\nunique = \"ID\"\n";
+        let config: Config = yaml.parse().unwrap();
+        assert_eq!(config.brackets, ["ID", "REF"]);
+        assert_eq!(config.tags, ["@", "#"]);
+        assert!(config.closures.is_empty());
+        assert_eq!(config.links, Some(vec!["implements".to_string()]));
+        assert_eq!(config.unique, Some(vec!["ID".to_string()]));
+        assert_eq!(config.schema, None);
+        assert_eq!(Config::from_toml(toml), Ok(config));
+    }
+
+    #[test]
+    fn test_config_errors() {
+        let error = "indicator:\n  brackets: ID".parse::<Config>().err();
+        assert_eq!(
+            error,
+            Some("unknown key `indicator` in the configuration".to_string())
+        );
+        let error = "indicators:\n  brackets: [ID, 3]".parse::<Config>().err();
+        assert_eq!(error, Some("`3` of `brackets` is no string".to_string()));
+        let error = "format: [yaml]".parse::<Config>().err();
+        assert_eq!(error, Some("`format: [yaml]` is no string".to_string()));
+    }
+
+    #[test]
+    fn test_config_find() {
+        let directory = temp_directory("test_config_find");
+        let nested = directory.join("docs").join("specs");
+        std::fs::create_dir_all(&nested).unwrap();
+        let path = directory.join("curt.toml");
+        std::fs::write(&path, "[lint]\nschema = \"schema.yaml\"\n").unwrap();
+        assert_eq!(Config::find(&nested), Some(path.clone()));
+        let config = Config::load(&path).unwrap();
+        let schema = directory.join("schema.yaml");
+        assert_eq!(config.schema, Some(schema.to_string_lossy().into_owned()));
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
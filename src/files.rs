@@ -0,0 +1,152 @@
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::WalkBuilder;
+use std::path::{Path, PathBuf};
+
+fn glob_set(globs: &[String]) -> std::result::Result<GlobSet, String> {
+    let mut set = GlobSetBuilder::new();
+    for glob in globs {
+        set.add(Glob::new(glob).map_err(|error| error.to_string())?);
+    }
+    set.build().map_err(|error| error.to_string())
+}
+
+/// Checks whether `relative` or its file name matches `globs`, with `parents` also any directory above
+fn matches(globs: &GlobSet, relative: &Path, parents: bool) -> bool {
+    let matches = |path: &Path| {
+        globs.is_match(path) || path.file_name().is_some_and(|name| globs.is_match(name))
+    };
+    if parents {
+        relative
+            .ancestors()
+            .filter(|path| *path != Path::new(""))
+            .any(matches)
+    } else {
+        matches(relative)
+    }
+}
+
+/// Lists all files below `paths`, filtered by `include` and `exclude` globs relative to each path
+///
+/// With `respect_ignores` files ignored by `.gitignore` or `.curtignore` are skipped,
+/// the globs only filter the files left. Hidden files are skipped either way.
+/// Returns the files sorted by path and the problems found while walking,
+/// like directories that can not be read.
+pub fn find_files(
+    paths: &[String],
+    include: &[String],
+    exclude: &[String],
+    respect_ignores: bool,
+) -> std::result::Result<(Vec<PathBuf>, Vec<String>), String> {
+    let included = glob_set(include)?;
+    let excluded = glob_set(exclude)?;
+    let mut files = Vec::new();
+    let mut problems = Vec::new();
+    for path in paths {
+        let mut walk = WalkBuilder::new(path);
+        walk.standard_filters(false)
+            .hidden(true)
+            .sort_by_file_name(|a, b| a.cmp(b));
+        if respect_ignores {
+            // Ignore files apply even outside of a git repository, `.curtignore` on top of them
+            walk.git_ignore(true)
+                .git_exclude(true)
+                .parents(true)
+                .require_git(false)
+                .add_custom_ignore_filename(".curtignore");
+        }
+        for entry in walk.build() {
+            let entry = match entry {
+                Ok(entry) if entry.file_type().is_some_and(|kind| kind.is_file()) => entry,
+                Ok(_) => continue,
+                Err(error) => {
+                    problems.push(error.to_string());
+                    continue;
+                }
+            };
+            // A file given as path is matched by its name
+            let relative = match entry.path().strip_prefix(path) {
+                Ok(relative) if relative != Path::new("") => relative,
+                _ => Path::new(entry.file_name()),
+            };
+            // Excluding a directory like `target` excludes all files within it
+            if (include.is_empty() || matches(&included, relative, false))
+                && !matches(&excluded, relative, true)
+            {
+                files.push(entry.into_path());
+            }
+        }
+    }
+    // Sorted by path, as the same file may be found via several paths
+    files.sort();
+    files.dedup();
+    Ok((files, problems))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::files::find_files;
+    use crate::test_support::temp_directory;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn names(directory: &Path, files: &[PathBuf]) -> Vec<String> {
+        files
+            .iter()
+            .map(|file| file.strip_prefix(directory).unwrap().display().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_find_files() {
+        let directory = temp_directory("find_files");
+        for file in &[
+            "a.md",
+            "b.rs",
+            ".hidden.md",
+            "target/build.md",
+            "vendor/spec.md",
+            "docs/c.md",
+            "docs/skip.md",
+        ] {
+            let file = directory.join(file);
+            fs::create_dir_all(file.parent().unwrap()).unwrap();
+            fs::write(file, "ID[A]").unwrap();
+        }
+        fs::write(directory.join(".gitignore"), "target/\n").unwrap();
+        fs::write(directory.join(".curtignore"), "vendor/\n").unwrap();
+        fs::write(directory.join("docs/.curtignore"), "skip.md\n").unwrap();
+        let paths = [directory.display().to_string()];
+
+        let (files, problems) = find_files(&paths, &[], &[], true).unwrap();
+        assert!(problems.is_empty());
+        assert_eq!(names(&directory, &files), ["a.md", "b.rs", "docs/c.md"]);
+
+        let (files, _) = find_files(&paths, &[], &[], false).unwrap();
+        assert_eq!(
+            names(&directory, &files),
+            [
+                "a.md",
+                "b.rs",
+                "docs/c.md",
+                "docs/skip.md",
+                "target/build.md",
+                "vendor/spec.md"
+            ]
+        );
+
+        let include = ["*.md".to_string()];
+        let exclude = ["docs".to_string()];
+        let (files, _) = find_files(&paths, &include, &exclude, true).unwrap();
+        assert_eq!(names(&directory, &files), ["a.md"]);
+
+        // The globs do not bring back files of the ignore files
+        let (files, _) = find_files(&paths, &include, &[], true).unwrap();
+        assert_eq!(names(&directory, &files), ["a.md", "docs/c.md"]);
+        let (files, _) = find_files(&paths, &["docs/*.md".to_string()], &[], false).unwrap();
+        assert_eq!(names(&directory, &files), ["docs/c.md", "docs/skip.md"]);
+
+        let error = find_files(&paths, &["a{".to_string()], &[], true).err();
+        assert!(error.is_some());
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,43 @@
+use crate::flow::to_flow;
+use yaml_rust::Yaml;
+
+/// 64 bit FNV-1a hash, which stays the same across platforms and compiler versions
+fn fnv1a(text: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in text.bytes() {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+/// Fingerprint of the attributes of an annotation, independent of its formatting and position
+///
+/// A captured `region` only counts by its text, so moving it around keeps the fingerprint.
+pub fn fingerprint(attributes: &Yaml) -> String {
+    let mut attributes = attributes.clone();
+    if let Yaml::Hash(hash) = &mut attributes {
+        if let Some(region) = hash.get_mut(&Yaml::String("region".to_string())) {
+            if let Yaml::String(text) = &region["text"] {
+                *region = Yaml::String(text.clone());
+            }
+        }
+    }
+    format!("{:016x}", fnv1a(&to_flow(&attributes)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fingerprint::fingerprint;
+    use yaml_rust::YamlLoader;
+
+    #[test]
+    fn test_fingerprint() {
+        let a = YamlLoader::load_from_str("{ID: A, caption: a b}").unwrap();
+        let b = YamlLoader::load_from_str("{ID:   A,\n caption: 'a b'}").unwrap();
+        let c = YamlLoader::load_from_str("{ID: A, caption: a c}").unwrap();
+        assert_eq!(fingerprint(&a[0]), fingerprint(&b[0]));
+        assert_ne!(fingerprint(&a[0]), fingerprint(&c[0]));
+        assert_eq!(fingerprint(&a[0]), "27110d715600433c");
+    }
+}
@@ -0,0 +1,93 @@
+use yaml_rust::{Yaml, YamlLoader};
+
+/// Reads `value` as the single value of a flow mapping, `None` if it would break the mapping
+fn read_flow_value(value: &str) -> Option<Yaml> {
+    let docs = YamlLoader::load_from_str(&format!("{{key: {}}}", value)).ok()?;
+    match docs.as_slice() {
+        [Yaml::Hash(hash)] if hash.len() == 1 => hash.get(&Yaml::String("key".into())).cloned(),
+        _ => None,
+    }
+}
+
+/// Writes `value` as a double quoted yaml string
+fn quote(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n");
+    format!("\"{}\"", escaped)
+}
+
+/// Writes a string as flow yaml, quotes it unless it is read back as the very same string
+fn flow_string(value: &str) -> String {
+    let plain = !value.is_empty()
+        && value.trim() == value
+        && !value.contains('\n')
+        && read_flow_value(value).as_ref().and_then(Yaml::as_str) == Some(value);
+    if plain {
+        value.to_string()
+    } else {
+        quote(value)
+    }
+}
+
+/// Writes the text `value` as flow yaml typed the way yaml reads it, so `3` stays a number,
+/// quotes it if it would break the surrounding mapping
+pub(crate) fn flow_value(value: &str) -> String {
+    let value = value.trim();
+    if value.is_empty() {
+        "~".to_string()
+    } else if read_flow_value(value).is_some() {
+        value.to_string()
+    } else {
+        quote(value)
+    }
+}
+
+/// Writes a single `key: value` entry of a mapping as flow yaml
+pub(crate) fn flow_entry(key: &Yaml, value: &Yaml) -> String {
+    format!("{}: {}", to_flow(key), to_flow(value))
+}
+
+/// Writes `yaml` in a single line, like `{ID: NAME, tags: [a, b]}`
+pub fn to_flow(yaml: &Yaml) -> String {
+    match yaml {
+        Yaml::String(value) => flow_string(value),
+        Yaml::Integer(value) => value.to_string(),
+        Yaml::Real(value) => value.clone(),
+        Yaml::Boolean(value) => value.to_string(),
+        Yaml::Array(values) => {
+            let values: Vec<String> = values.iter().map(to_flow).collect();
+            format!("[{}]", values.join(", "))
+        }
+        Yaml::Hash(hash) => {
+            let entries: Vec<String> = hash
+                .iter()
+                .map(|(key, value)| flow_entry(key, value))
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+        _ => "~".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::flow::{flow_value, to_flow};
+    use yaml_rust::YamlLoader;
+
+    #[test]
+    fn test_to_flow() {
+        let text = "{ID: REQ::A, count: 3, ratio: 0.5, done: true, none: ~, list: [a, \"3\", \"b, c\"], nested: {x: \"line\\nbreak\"}}";
+        let docs = YamlLoader::load_from_str(text).unwrap();
+        assert_eq!(to_flow(&docs[0]), text);
+    }
+
+    #[test]
+    fn test_flow_value() {
+        assert_eq!(flow_value(" 3 "), "3");
+        assert_eq!(flow_value("a: b"), "\"a: b\"");
+        assert_eq!(flow_value("say \"a, b\""), "\"say \\\"a, b\\\"\"");
+        assert_eq!(flow_value(""), "~");
+    }
+}
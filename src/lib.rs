@@ -1,19 +1,57 @@
+extern crate globset;
+extern crate ignore;
+extern crate regex;
+extern crate toml;
 extern crate yaml_rust;
 
+pub mod config;
+pub mod files;
+pub mod fingerprint;
+pub mod flow;
+pub mod lint;
+pub mod merge;
+pub mod namespace;
+pub mod nesting;
+pub mod normalize;
+pub mod output;
+pub mod query;
+pub mod region;
+pub mod schema;
+pub mod suspect;
+pub mod tags;
+pub mod trace;
+
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+pub use tags::{TagScope, TagStyle};
 use yaml_rust::{Yaml, YamlLoader};
 
 // ID[IMPL::yaml-extraction::]
 /// Contains identifier checks and results from usage
 pub struct YogurtYaml<'a> {
     ident_checks: Vec<IdentChecker<'a>>,
+    context: ScanContext,
     results: Vec<Result>,
 }
 
 /// Results found via extraction from strings
+#[derive(Clone, Debug)]
 pub struct Result {
     text: String,
+    ident: String,
+    payload: String,
+    range: IdentRange,
     start: usize,
     end: usize,
+    line: usize,
+    column: usize,
+    end_line: usize,
+    end_column: usize,
+    paragraph: usize,
+    path: Option<String>,
+    kind: Payload,
 }
 
 /// Access results via convenient functions
@@ -44,14 +82,129 @@ impl Result {
         self.end
     }
 
+    /// return the identifier the result was found by, e.g. `ID` or `#`
+    pub fn get_ident(&self) -> &str {
+        &self.ident
+    }
+
+    /// return the name of an annotation, e.g. `A` of `ID[A, caption: a]`
+    pub fn get_name(&self) -> String {
+        trace::parse_annotation(self).0
+    }
+
+    /// return the content following the identifier without delimiters
+    pub fn get_payload(&self) -> &str {
+        &self.payload
+    }
+
+    pub fn get_range(&self) -> IdentRange {
+        self.range
+    }
+
+    /// return whether the payload is read as yaml or as plain words
+    pub fn get_payload_kind(&self) -> Payload {
+        self.kind
+    }
+
+    /// return the line the result starts at, counted from 1
+    pub fn get_line(&self) -> usize {
+        self.line
+    }
+
+    /// return the column the result starts at, counted from 1
+    pub fn get_column(&self) -> usize {
+        self.column
+    }
+
+    /// return the line the result ends at, counted from 1
+    pub fn get_end_line(&self) -> usize {
+        self.end_line
+    }
+
+    /// return the column behind the last char of the result, counted from 1
+    pub fn get_end_column(&self) -> usize {
+        self.end_column
+    }
+
+    /// return the path of the file the result was found in, `None` for other input
+    pub fn get_path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    /// return where the result starts as `path:line:column`, `<stdin>:line:column` for other input
+    pub fn get_location(&self) -> String {
+        let path = self.get_path().unwrap_or("<stdin>");
+        format!("{}:{}:{}", path, self.line, self.column)
+    }
+
+    /// return the path, line and column to order results by
+    pub(crate) fn position(&self) -> (Option<&str>, usize, usize) {
+        (self.get_path(), self.line, self.column)
+    }
+
+    /// Replace the payload of an annotation and write its text anew
+    pub(crate) fn set_payload(&mut self, payload: String) {
+        self.text = format_annotation(&self.ident, &payload, self.kind);
+        self.payload = payload;
+    }
+
+    /// Create a result without any information about its identifier
     pub fn new(text: String, start: usize, end: usize) -> Result {
-        Result { text, start, end }
+        Result {
+            text,
+            ident: String::new(),
+            payload: String::new(),
+            range: IdentRange::Brackets,
+            start,
+            end,
+            line: 1,
+            column: 1,
+            end_line: 1,
+            end_column: 1,
+            paragraph: 0,
+            path: None,
+            kind: Payload::Yaml,
+        }
+    }
+}
+
+/// Chars ending a `IdentRange::Tag` if no other terminators are configured
+pub const TAG_TERMINATORS: [char; 4] = [' ', '\n', ',', '.'];
+
+/// Chars also ending a `IdentRange::Tag` with `TagRules::punctuation`
+pub const TAG_PUNCTUATION: [char; 5] = ['\t', ';', ')', ']', '}'];
+
+/// Rules deciding where a `IdentRange::Tag` may start
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct TagRules {
+    /// Tags need whitespace, the start of input or one of `([{"',;` in front
+    pub boundary: bool,
+    /// Tags inside urls like `https://x/y#section` or emails like `dev@example.com` are skipped
+    pub skip_urls: bool,
+    /// Tags at the very start of a line, like `#Title` or `#include`, are skipped
+    pub skip_headings: bool,
+    /// Tags also end at `TAG_PUNCTUATION`, like `#a` in `(see #a)`
+    pub punctuation: bool,
+}
+
+impl Default for TagRules {
+    fn default() -> TagRules {
+        TagRules {
+            boundary: true,
+            skip_urls: true,
+            skip_headings: false,
+            punctuation: false,
+        }
     }
 }
 
 pub struct Indicators<'a> {
     ident_strings: &'a [&'a str],
     range: IdentRange,
+    terminators: &'a [char],
+    tag_rules: TagRules,
+    tag_style: TagStyle,
+    payload: Payload,
 }
 
 impl<'a> Indicators<'a> {
@@ -59,8 +212,106 @@ impl<'a> Indicators<'a> {
         Indicators {
             ident_strings,
             range,
+            terminators: &TAG_TERMINATORS,
+            tag_rules: TagRules::default(),
+            tag_style: TagStyle::Named,
+            payload: Payload::Yaml,
         }
     }
+
+    /// Replace the chars ending a tag, e.g. without `.` to keep `#v1.2` in one piece
+    pub fn with_terminators(mut self, terminators: &'a [char]) -> Indicators<'a> {
+        self.terminators = terminators;
+        self
+    }
+
+    /// Replace the rules deciding where a tag may start
+    pub fn with_tag_rules(mut self, tag_rules: TagRules) -> Indicators<'a> {
+        self.tag_rules = tag_rules;
+        self
+    }
+
+    /// Replace the way tags are written as yaml
+    pub fn with_tag_style(mut self, tag_style: TagStyle) -> Indicators<'a> {
+        self.tag_style = tag_style;
+        self
+    }
+
+    /// Replace the way the payload of annotations is read, tags are not affected
+    pub fn with_payload(mut self, payload: Payload) -> Indicators<'a> {
+        self.payload = payload;
+        self
+    }
+}
+
+/// `Indicators` owning their identifiers and terminators
+#[derive(Clone, Debug)]
+struct CompiledIndicators {
+    ident_strings: Vec<String>,
+    range: IdentRange,
+    terminators: Vec<char>,
+    tag_rules: TagRules,
+    tag_style: TagStyle,
+    payload: Payload,
+}
+
+/// Indicators compiled once and shared, e.g. between threads extracting different files
+#[derive(Clone, Debug)]
+pub struct Extractor {
+    indicator_lists: Vec<CompiledIndicators>,
+}
+
+impl Extractor {
+    pub fn new(indicator_lists: &[Indicators]) -> Extractor {
+        let indicator_lists = indicator_lists
+            .iter()
+            .map(|indicators| CompiledIndicators {
+                ident_strings: indicators
+                    .ident_strings
+                    .iter()
+                    .map(|ident| ident.to_string())
+                    .collect(),
+                range: indicators.range,
+                terminators: indicators.terminators.to_vec(),
+                tag_rules: indicators.tag_rules,
+                tag_style: indicators.tag_style,
+                payload: indicators.payload,
+            })
+            .collect();
+        Extractor { indicator_lists }
+    }
+
+    /// Create a new curt instance with fresh state, borrowing the indicators of the extractor
+    pub fn curt(&self) -> YogurtYaml<'_> {
+        let mut ident_checks = Vec::new();
+        for indicators in &self.indicator_lists {
+            let ident_strings: Vec<&str> = indicators
+                .ident_strings
+                .iter()
+                .map(String::as_str)
+                .collect();
+            ident_checks.extend(create_ident_checks(
+                &ident_strings,
+                indicators.range,
+                &indicators.terminators,
+                indicators.tag_rules,
+                indicators.tag_style,
+                indicators.payload,
+            ));
+        }
+        YogurtYaml {
+            ident_checks,
+            context: ScanContext::new(),
+            results: Vec::new(),
+        }
+    }
+
+    /// Extract all results of `s`, annotations still open at the end are dropped
+    pub fn extract(&self, s: &str) -> Vec<Result> {
+        let mut curt = self.curt();
+        curt.curt_clear(&mut s.to_string());
+        curt.results
+    }
 }
 
 /// Implements YogurtYaml functions
@@ -72,21 +323,34 @@ impl<'a> YogurtYaml<'a> {
             ident_checks.extend(create_ident_checks(
                 indicator_list.ident_strings,
                 indicator_list.range,
+                indicator_list.terminators,
+                indicator_list.tag_rules,
+                indicator_list.tag_style,
+                indicator_list.payload,
             ));
         }
         let results = Vec::new();
         YogurtYaml {
             ident_checks,
+            context: ScanContext::new(),
             results,
         }
     }
 
     /// Create a new curt instance
     pub fn new_from_str(indicators: &'a [&'a str]) -> YogurtYaml<'a> {
-        let ident_checks = create_ident_checks(indicators, IdentRange::Brackets);
+        let ident_checks = create_ident_checks(
+            indicators,
+            IdentRange::Brackets,
+            &TAG_TERMINATORS,
+            TagRules::default(),
+            TagStyle::Named,
+            Payload::Yaml,
+        );
         let results = Vec::new();
         YogurtYaml {
             ident_checks,
+            context: ScanContext::new(),
             results,
         }
     }
@@ -94,18 +358,41 @@ impl<'a> YogurtYaml<'a> {
     // ID[IMPL::Multiline_Support, implements: REQ::Multi_Line]
     /// Extract yaml from string
     pub fn curt(&mut self, s: &str) {
-        self.results
-            .extend(cut_yaml_unchecked(&mut self.ident_checks, s));
+        self.results.extend(cut_yaml_unchecked(
+            &mut self.ident_checks,
+            &mut self.context,
+            s,
+        ));
     }
 
     /// Extracts yaml and clears string if not open
     pub fn curt_clear(&mut self, s: &mut String) {
-        self.results.extend(cut_yaml(&mut self.ident_checks, s));
+        self.results
+            .extend(cut_yaml(&mut self.ident_checks, &mut self.context, s));
         if !self.reset_open() {
             s.clear();
         }
     }
 
+    /// Extract yaml from the file at `path` with fresh state and return its text
+    ///
+    /// Annotations still open at the end of the file are dropped, each result knows the path.
+    pub fn curt_file(&mut self, path: &Path) -> io::Result<String> {
+        let text = fs::read_to_string(path)?;
+        for ident_check in &mut self.ident_checks {
+            reset(ident_check);
+        }
+        self.context = ScanContext::new();
+        let found = self.results.len();
+        self.curt_clear(&mut text.clone());
+        self.reset_open();
+        let path = path.to_string_lossy().to_string();
+        for result in &mut self.results[found..] {
+            result.path = Some(path.clone());
+        }
+        Ok(text)
+    }
+
     /// Return results
     pub fn get_results(&self) -> &Vec<Result> {
         &self.results
@@ -116,6 +403,43 @@ impl<'a> YogurtYaml<'a> {
         self.results.clear();
     }
 
+    /// Collect all tags of each `scope` into a single `{tags: [...]}` result
+    pub fn aggregate_tags(&mut self, scope: TagScope) {
+        let results = std::mem::take(&mut self.results);
+        self.results = tags::aggregate_tags(results, scope);
+    }
+
+    /// Move annotations within the payload of another one into it, e.g. `ID[A, depends: REF[B]]`
+    pub fn nest_annotations(&mut self) {
+        let results = std::mem::take(&mut self.results);
+        self.results = nesting::nest_annotations(results);
+    }
+
+    /// Resolve relative names like `ID[::name]` against the most recent absolute ID
+    pub fn resolve_namespaces(&mut self, config: &trace::TraceConfig) {
+        namespace::resolve_namespaces(&mut self.results, config);
+    }
+
+    /// Merge each `ADD[NAME, ...]` into its `ID[NAME, ...]`, return the problems found on the way
+    pub fn merge_additions(&mut self, config: &merge::MergeConfig) -> Vec<lint::Diagnostic> {
+        let results = std::mem::take(&mut self.results);
+        let (results, diagnostics) = merge::merge_additions(results, config);
+        self.results = results;
+        diagnostics
+    }
+
+    /// Capture the text of each region `ID[X, region: true] ... END[X]` out of `source`
+    pub fn capture_regions(
+        &mut self,
+        source: &str,
+        config: &region::RegionConfig,
+    ) -> Vec<lint::Diagnostic> {
+        let results = std::mem::take(&mut self.results);
+        let (results, diagnostics) = region::capture_regions(results, source, config);
+        self.results = results;
+        diagnostics
+    }
+
     /// Checks whether there is any not `SemanticPosition::Out` containing `ident_check` in the list of `ident_checks`
     pub fn is_open(&self) -> bool {
         for ident_check in &self.ident_checks {
@@ -131,6 +455,7 @@ impl<'a> YogurtYaml<'a> {
         for ident_check in &mut self.ident_checks {
             reset(ident_check);
         }
+        self.context = ScanContext::new();
         self.clear_results();
     }
 
@@ -154,10 +479,19 @@ struct IdentChecker<'a> {
     first_char: char,
     begin_char: char,
     end_char: char,
+    terminators: &'a [char],
+    tag_rules: TagRules,
+    tag_style: TagStyle,
+    payload: Payload,
     // mut:
     semantic_position: SemanticPosition,
     length: usize,
     closures: i32,
+    line: usize,
+    column: usize,
+    paragraph: usize,
+    indent: usize,
+    continuation: usize,
 }
 
 #[derive(PartialEq)]
@@ -169,19 +503,96 @@ enum SemanticPosition {
     InDoubleQuote,
     InSingleQuoteEscaped,
     InDoubleQuoteEscaped,
+    TagLineEnd,
     Done,
 }
 
-fn check_out(ident_check: &mut IdentChecker, c: char) {
-    if c == ident_check.first_char {
+/// Surroundings of the current char, kept between calls to allow streaming
+#[derive(Copy, Clone)]
+struct ScanContext {
+    previous: char,
+    url_marker: usize,
+    line: usize,
+    column: usize,
+    paragraph: usize,
+    indent: usize,
+    blank_line: bool,
+    carriage_return: bool,
+}
+
+impl ScanContext {
+    fn new() -> ScanContext {
+        ScanContext {
+            previous: '\n', // The start of input behaves like the start of a line
+            url_marker: 0,
+            line: 1,
+            column: 1,
+            paragraph: 0,
+            indent: 0,
+            blank_line: true,
+            carriage_return: false,
+        }
+    }
+
+    /// Remember `c` as previous char, move the position behind it and track whether it is part of an url
+    fn update(&mut self, c: char) {
+        if c == '\n' {
+            if self.blank_line {
+                self.paragraph += 1;
+            }
+            self.line += 1;
+            self.column = 1;
+            self.indent = 0;
+            self.blank_line = true;
+        } else {
+            if self.blank_line && (c == ' ' || c == '\t') {
+                self.indent += 1;
+            }
+            self.column += 1;
+            self.blank_line &= c.is_whitespace();
+        }
+        self.url_marker = match (self.url_marker, c) {
+            (_, c) if c.is_whitespace() => 0,
+            (3, _) => 3,
+            (_, ':') => 1,
+            (1, '/') => 2,
+            (2, '/') => 3,
+            _ => 0,
+        };
+        self.previous = c;
+    }
+
+    /// Checks whether a tag starting with `c` is allowed by `tag_rules`
+    fn allows_tag(&self, tag_rules: &TagRules, c: char) -> bool {
+        let previous = self.previous;
+        let is_boundary = previous.is_whitespace() || "([{\"',;".contains(previous);
+        let is_email = c == '@' && (previous.is_alphanumeric() || "._-+".contains(previous));
+        !(tag_rules.boundary && !is_boundary
+            || tag_rules.skip_urls && (self.url_marker == 3 || is_email)
+            || tag_rules.skip_headings && previous == '\n')
+    }
+}
+
+fn check_out(ident_check: &mut IdentChecker, context: &ScanContext, c: char) {
+    if c == ident_check.first_char
+        && (ident_check.range != IdentRange::Tag || context.allows_tag(&ident_check.tag_rules, c))
+    {
         ident_check.length = 1;
         ident_check.semantic_position = SemanticPosition::Ident;
+        mark_start(ident_check, context);
     }
 }
 
-fn clean_up(ident_check: &mut IdentChecker, c: char) {
+fn mark_start(ident_check: &mut IdentChecker, context: &ScanContext) {
+    ident_check.line = context.line;
+    ident_check.column = context.column;
+    ident_check.paragraph = context.paragraph;
+    ident_check.indent = context.indent;
+}
+
+fn clean_up(ident_check: &mut IdentChecker, context: &ScanContext, c: char) {
     reset(ident_check);
-    check_out(ident_check, c); // Could be the start of a ident
+    check_out(ident_check, context, c); // Could be the start of a ident
 }
 
 fn reset(ident_check: &mut IdentChecker) {
@@ -190,14 +601,14 @@ fn reset(ident_check: &mut IdentChecker) {
     ident_check.semantic_position = SemanticPosition::Out;
 }
 
-fn check_ident(ident_check: &mut IdentChecker, c: char) {
+fn check_ident(ident_check: &mut IdentChecker, context: &ScanContext, c: char) {
     let check_size = ident_check.ident.len() < ident_check.length;
     if check_size {
         if ident_check.begin_char == c {
             ident_check.semantic_position = SemanticPosition::In;
             ident_check.closures = 1;
         } else {
-            clean_up(ident_check, c);
+            clean_up(ident_check, context, c);
         }
     } else if c
         != ident_check
@@ -206,22 +617,46 @@ fn check_ident(ident_check: &mut IdentChecker, c: char) {
             .nth(ident_check.length - 1)
             .unwrap()
     {
-        clean_up(ident_check, c);
+        clean_up(ident_check, context, c);
     }
 }
 
-fn check_ident_tag(ident_check: &mut IdentChecker, c: char) {
-    if c == ident_check.begin_char {
+fn check_ident_tag(ident_check: &mut IdentChecker, context: &ScanContext, c: char) {
+    let prefix_length = ident_check.ident.chars().count();
+    if ident_check.length <= prefix_length {
+        // Still matching a multi char prefix like `TODO`
+        if c != ident_check
+            .ident
+            .chars()
+            .nth(ident_check.length - 1)
+            .unwrap()
+        {
+            clean_up(ident_check, context, c);
+        }
+    } else if c == ident_check.begin_char {
         ident_check.semantic_position = SemanticPosition::In;
-    } else if c == ' ' || c == '\n' || c == ',' || c == '.' {
-        if ident_check.length > 2 {
+    } else if c == ident_check.end_char
+        || ident_check.terminators.contains(&c)
+        || ident_check.tag_rules.punctuation && TAG_PUNCTUATION.contains(&c)
+    {
+        if ident_check.length > prefix_length + 1 {
             ident_check.semantic_position = SemanticPosition::Done;
         } else {
             reset(ident_check);
         }
-    } else if c == ident_check.first_char {
+    } else if c == ident_check.first_char && ident_check.length == prefix_length + 1 {
+        // Runs like `##` restart the tag at the last prefix
         ident_check.length = 1;
-        ident_check.semantic_position = SemanticPosition::Ident;
+        mark_start(ident_check, context);
+    }
+}
+
+/// Checks whether a tag already has content and would be complete at the end of input
+fn has_tag_content(ident_check: &IdentChecker) -> bool {
+    match ident_check.semantic_position {
+        SemanticPosition::Ident => ident_check.length > ident_check.ident.chars().count() + 1,
+        SemanticPosition::Out | SemanticPosition::Done => false,
+        _ => true,
     }
 }
 
@@ -233,6 +668,8 @@ fn check_in(ident_check: &mut IdentChecker, c: char) {
         check_end(ident_check);
     } else if c == begin {
         ident_check.closures += 1;
+    } else if ident_check.payload == Payload::Words {
+        // Quotes are just chars within words, e.g. the apostrophe of `don't`
     } else if c == '\'' {
         ident_check.semantic_position = SemanticPosition::InSingleQuote;
     } else if c == '"' {
@@ -240,6 +677,30 @@ fn check_in(ident_check: &mut IdentChecker, c: char) {
     }
 }
 
+/// Checks whether a tag of any of `ident_checks` starts at `rest` after whitespace
+///
+/// The value of a tag like `@count: 3 @note: a, b` ends in front of such a tag.
+fn starts_tag(ident_checks: &[IdentChecker], context: &ScanContext, rest: &str) -> bool {
+    context.previous.is_whitespace()
+        && ident_checks.iter().any(|ident_check| {
+            ident_check.range == IdentRange::Tag
+                && rest.starts_with(ident_check.ident)
+                && rest[ident_check.ident.len()..]
+                    .chars()
+                    .next()
+                    .is_some_and(|next| !next.is_whitespace() && next != ':')
+                && context.allows_tag(&ident_check.tag_rules, ident_check.first_char)
+        })
+}
+
+/// Tag values end with the line unless it ends with `\` or the next line is indented further
+fn check_in_tag(ident_check: &mut IdentChecker, context: &ScanContext, c: char) {
+    if c == ident_check.end_char && context.previous != '\\' {
+        ident_check.semantic_position = SemanticPosition::TagLineEnd;
+        ident_check.continuation = 0;
+    }
+}
+
 fn check_single_quote(ident_check: &mut IdentChecker, c: char) {
     if c == '\'' {
         ident_check.semantic_position = SemanticPosition::In;
@@ -262,40 +723,114 @@ fn check_end(ident_check: &mut IdentChecker) {
     }
 }
 
+/// Replaces `\r\n` and lone `\r` by `\n` to get the same results on every os
+fn normalize_line_breaks(raw: &str) -> String {
+    if raw.contains('\r') {
+        raw.replace("\r\n", "\n").replace('\r', "\n")
+    } else {
+        raw.to_string()
+    }
+}
+
+/// Writes an annotation like `ID[payload]` as yaml mapping
+fn format_annotation(ident: &str, payload: &str, kind: Payload) -> String {
+    match kind {
+        Payload::Yaml => format!("{{{}: {}}}", ident, payload),
+        Payload::Words => {
+            let words = Yaml::String(payload.trim().to_string());
+            format!("{{{}: {}}}", ident, flow::to_flow(&words))
+        }
+    }
+}
+
+/// Line and column behind `raw` if it starts at `line` and `column`
+fn end_position(line: usize, column: usize, raw: &str) -> (usize, usize) {
+    raw.chars()
+        .fold((line, column), |(line, column), c| match c {
+            '\n' => (line + 1, 1),
+            _ => (line, column + 1),
+        })
+}
+
 fn add_result(results: &mut Vec<Result>, ident_check: &mut IdentChecker, s: &str, i: usize) {
-    let end = i - 1;
-    let length;
-    match ident_check.length.checked_sub(2) {
-        Some(a) => length = a,
-        None => length = 0,
-    }; // TODO: Minus 2 is a bit odd ..
+    let mut end = i - 1;
+    let length = ident_check.length.saturating_sub(2); // TODO: Minus 2 is a bit odd ..
     let start = end.checked_sub(length).unwrap();
 
-    let mut text: String = s.chars().skip(start).take(length).collect();
-    text = text.replacen(ident_check.begin_char, ": ", 1);
-    text.insert(0, '{');
-    text.push('}');
-    results.push(Result { text, start, end });
+    let raw: String = s.chars().skip(start).take(length).collect();
+    if ident_check.range == IdentRange::Tag {
+        end = start + raw.trim_end().chars().count();
+    }
+    let raw = normalize_line_breaks(&raw);
+    let (end_line, end_column) = if ident_check.range == IdentRange::Tag {
+        end_position(ident_check.line, ident_check.column, raw.trim_end())
+    } else {
+        // The closing char is not part of `raw`
+        let (line, column) = end_position(ident_check.line, ident_check.column, &raw);
+        (line, column + 1)
+    };
+    let text;
+    let payload: String;
+    if ident_check.range == IdentRange::Tag {
+        let raw = tags::fold_lines(&raw);
+        payload = raw
+            .chars()
+            .skip(ident_check.ident.chars().count())
+            .collect();
+        text = tags::format_tag(&raw, &payload, ident_check.tag_style);
+    } else {
+        payload = raw
+            .chars()
+            .skip(ident_check.ident.chars().count() + 1)
+            .collect();
+        text = format_annotation(ident_check.ident, &payload, ident_check.payload);
+    }
+    results.push(Result {
+        text,
+        ident: ident_check.ident.to_string(),
+        payload,
+        range: ident_check.range,
+        start,
+        end,
+        line: ident_check.line,
+        column: ident_check.column,
+        end_line,
+        end_column,
+        paragraph: ident_check.paragraph,
+        path: None,
+        kind: ident_check.payload,
+    });
 }
 
 pub fn cut_yaml_ident_strings(ident_strings: &[&str], s: &str) -> Vec<Result> {
-    let mut ident_checks = create_ident_checks(ident_strings, IdentRange::Brackets);
-    cut_yaml(&mut ident_checks, s)
+    let mut ident_checks = create_ident_checks(
+        ident_strings,
+        IdentRange::Brackets,
+        &TAG_TERMINATORS,
+        TagRules::default(),
+        TagStyle::Named,
+        Payload::Yaml,
+    );
+    cut_yaml(&mut ident_checks, &mut ScanContext::new(), s)
 }
 
 fn check_ident_checks(ident_checks: &mut Vec<IdentChecker>, s: &str, results: &mut Vec<Result>) {
+    let count = s.chars().count();
     for ident_check in ident_checks {
         ident_check.length += 1;
-        if ident_check.semantic_position == SemanticPosition::Done
-            || (ident_check.range == IdentRange::Tag
-                && ident_check.semantic_position != SemanticPosition::Out)
-        {
-            add_result(results, ident_check, s, s.len());
+        if ident_check.semantic_position == SemanticPosition::Done {
+            add_result(results, ident_check, s, count);
+            reset(ident_check);
+        } else if ident_check.range == IdentRange::Tag && has_tag_content(ident_check) {
+            // The end of input terminates the tag like a terminator would
+            ident_check.length += 1;
+            add_result(results, ident_check, s, count + 1);
+            reset(ident_check);
         }
     }
 }
 
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum IdentRange {
     Tag,
     Brackets,
@@ -304,78 +839,168 @@ pub enum IdentRange {
     Rounds,
 }
 
-fn create_ident_checks<'a>(ident_strings: &'a [&'a str], range: IdentRange) -> Vec<IdentChecker> {
-    let mut ident_checks = Vec::new();
-    let begin_char;
-    let end_char;
+impl FromStr for IdentRange {
+    type Err = String;
 
-    match range {
-        IdentRange::Closures => {
-            begin_char = '{';
-            end_char = '}';
+    fn from_str(s: &str) -> std::result::Result<IdentRange, String> {
+        match s {
+            "tag" => Ok(IdentRange::Tag),
+            "brackets" => Ok(IdentRange::Brackets),
+            "closures" => Ok(IdentRange::Closures),
+            "crickets" => Ok(IdentRange::Crickets),
+            "rounds" => Ok(IdentRange::Rounds),
+            _ => Err(format!("unknown range `{}`", s)),
         }
-        IdentRange::Brackets => {
-            begin_char = '[';
-            end_char = ']';
-        }
-        IdentRange::Crickets => {
-            begin_char = '<';
-            end_char = '>';
+    }
+}
+
+/// Ways to read the payload of an annotation like `IDENT[payload]`
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Payload {
+    /// `ID[A, x: "]"]` becomes `{ID: A, x: "]"}`, delimiters within quotes are skipped
+    Yaml,
+    /// `REF(don't care)` becomes `{REF: "don't care"}`, quotes are plain chars
+    Words,
+}
+
+impl FromStr for Payload {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Payload, String> {
+        match s {
+            "yaml" => Ok(Payload::Yaml),
+            "words" => Ok(Payload::Words),
+            _ => Err(format!("unknown payload `{}`", s)),
         }
-        IdentRange::Rounds => {
-            begin_char = '(';
-            end_char = ')';
+    }
+}
+
+impl IdentRange {
+    /// return the name the range is read from, e.g. `brackets`
+    pub fn get_name(self) -> &'static str {
+        match self {
+            IdentRange::Tag => "tag",
+            IdentRange::Brackets => "brackets",
+            IdentRange::Closures => "closures",
+            IdentRange::Crickets => "crickets",
+            IdentRange::Rounds => "rounds",
         }
-        IdentRange::Tag => {
-            begin_char = ':';
-            end_char = '\n';
+    }
+
+    /// return the chars opening and closing the range, e.g. `[` and `]`
+    pub(crate) fn delimiters(self) -> (char, char) {
+        match self {
+            IdentRange::Closures => ('{', '}'),
+            IdentRange::Brackets => ('[', ']'),
+            IdentRange::Crickets => ('<', '>'),
+            IdentRange::Rounds => ('(', ')'),
+            IdentRange::Tag => (':', '\n'),
         }
     }
+}
+
+fn create_ident_checks<'a>(
+    ident_strings: &[&'a str],
+    range: IdentRange,
+    terminators: &'a [char],
+    tag_rules: TagRules,
+    tag_style: TagStyle,
+    payload: Payload,
+) -> Vec<IdentChecker<'a>> {
+    let mut ident_checks = Vec::new();
+    let (begin_char, end_char) = range.delimiters();
 
     for ident in ident_strings {
         ident_checks.push(IdentChecker {
             range,
             ident,
-            first_char: ident.chars().nth(0).unwrap(),
+            first_char: ident.chars().next().unwrap(),
             begin_char,
             end_char,
+            terminators,
+            tag_rules,
+            tag_style,
+            payload,
             semantic_position: SemanticPosition::Out,
             length: 0,
             closures: 0,
+            line: 1,
+            column: 1,
+            paragraph: 0,
+            indent: 0,
+            continuation: 0,
         });
     }
     ident_checks
 }
 
-fn cut_yaml(ident_checks: &mut Vec<IdentChecker>, s: &str) -> Vec<Result> {
-    let mut results = cut_yaml_unchecked(ident_checks, s);
+fn cut_yaml(
+    ident_checks: &mut Vec<IdentChecker>,
+    context: &mut ScanContext,
+    s: &str,
+) -> Vec<Result> {
+    let mut results = cut_yaml_unchecked(ident_checks, context, s);
     check_ident_checks(ident_checks, s, &mut results);
     results
 }
 
-fn cut_yaml_unchecked(ident_checks: &mut Vec<IdentChecker>, s: &str) -> Vec<Result> {
+fn cut_yaml_unchecked(
+    ident_checks: &mut Vec<IdentChecker>,
+    context: &mut ScanContext,
+    s: &str,
+) -> Vec<Result> {
     let mut results = Vec::new();
-    for (i, c) in s.chars().enumerate() {
+    for (i, (offset, c)) in s.char_indices().enumerate() {
+        let after_carriage_return = context.carriage_return;
+        context.carriage_return = c == '\r';
+        if c == '\n' && after_carriage_return {
+            // The `\r` of `\r\n` already was the line break
+            for ident_check in &mut *ident_checks {
+                ident_check.length += 1;
+            }
+            continue;
+        }
+        let c = if c == '\r' { '\n' } else { c };
+        let tag_start = starts_tag(ident_checks, context, &s[offset..]);
         for ident_check in &mut *ident_checks {
             ident_check.length += 1;
             match ident_check.semantic_position {
                 SemanticPosition::Out => {
-                    check_out(ident_check, c);
+                    check_out(ident_check, context, c);
                 }
                 SemanticPosition::Ident => {
                     if ident_check.range == IdentRange::Tag {
-                        check_ident_tag(ident_check, c);
+                        check_ident_tag(ident_check, context, c);
                     } else {
-                        check_ident(ident_check, c);
+                        check_ident(ident_check, context, c);
                     }
                 }
                 SemanticPosition::In => {
-                    if c == ident_check.end_char && ident_check.range == IdentRange::Tag {
-                        ident_check.semantic_position = SemanticPosition::Done;
+                    if ident_check.range == IdentRange::Tag && tag_start {
+                        add_result(&mut results, ident_check, s, i);
+                        reset(ident_check);
+                        check_out(ident_check, context, c);
+                    } else if ident_check.range == IdentRange::Tag {
+                        check_in_tag(ident_check, context, c);
                     } else {
                         check_in(ident_check, c);
                     }
                 }
+                SemanticPosition::TagLineEnd => {
+                    if c == ' ' || c == '\t' {
+                        ident_check.continuation += 1;
+                    } else if c != '\n'
+                        && !tag_start
+                        && ident_check.continuation > ident_check.indent
+                    {
+                        ident_check.semantic_position = SemanticPosition::In;
+                    } else {
+                        // The tag already ended with the previous line
+                        add_result(&mut results, ident_check, s, i);
+                        reset(ident_check);
+                        check_out(ident_check, context, c);
+                    }
+                }
                 SemanticPosition::InSingleQuote => {
                     check_single_quote(ident_check, c);
                 }
@@ -391,15 +1016,84 @@ fn cut_yaml_unchecked(ident_checks: &mut Vec<IdentChecker>, s: &str) -> Vec<Resu
                 SemanticPosition::Done => {
                     add_result(&mut results, ident_check, s, i);
                     reset(ident_check);
-                    check_out(ident_check, c);
+                    check_out(ident_check, context, c);
                 }
             }
         }
+        context.update(c);
     }
     results
 }
 
+/// Helpers shared by the tests of all modules
+#[cfg(test)]
+pub(crate) mod test_support {
+    use crate::lint::Diagnostic;
+    use crate::{IdentRange, Indicators, Result, TagRules, YogurtYaml};
+    use std::path::PathBuf;
+
+    /// Extracts `test_data` with `indicator_lists`, `process` runs before the results are taken
+    pub fn curt_indicators<F>(
+        indicator_lists: &[Indicators],
+        test_data: &str,
+        process: F,
+    ) -> Vec<Result>
+    where
+        F: FnOnce(&mut YogurtYaml),
+    {
+        let mut curt = YogurtYaml::new(indicator_lists);
+        curt.curt_clear(&mut test_data.to_string());
+        process(&mut curt);
+        curt.get_results().clone()
+    }
+
+    /// Extracts `test_data` like curt-extract with the `brackets` identifiers and the tags
+    /// `#` and `@`, `process` runs before the results are taken
+    pub fn curt<F>(brackets: &[&str], test_data: &str, process: F) -> Vec<Result>
+    where
+        F: FnOnce(&mut YogurtYaml),
+    {
+        let tag_rules = TagRules {
+            punctuation: true,
+            ..TagRules::default()
+        };
+        let indicator_lists = vec![
+            Indicators::new(brackets, IdentRange::Brackets),
+            Indicators::new(&["#", "@"], IdentRange::Tag).with_tag_rules(tag_rules),
+        ];
+        curt_indicators(&indicator_lists, test_data, process)
+    }
+
+    /// The yaml text of each result
+    pub fn texts(results: &[Result]) -> Vec<String> {
+        results
+            .iter()
+            .map(|result| result.get_text().clone())
+            .collect()
+    }
+
+    /// Each diagnostic as printed by curt-extract
+    pub fn prints(diagnostics: &[Diagnostic]) -> Vec<String> {
+        diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.get_print())
+            .collect()
+    }
+
+    /// An empty directory for the test `name`, its own per process so runs do not collide
+    pub fn temp_directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("curt_{}_{}", std::process::id(), name));
+        if directory.exists() {
+            std::fs::remove_dir_all(&directory).unwrap();
+        }
+        std::fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+}
+
 #[cfg(test)]
+// The tests of the first releases are kept as they were written
+#[allow(clippy::unnecessary_to_owned, clippy::vec_init_then_push)]
 mod tests {
     use crate::cut_yaml_ident_strings;
 
@@ -545,10 +1239,10 @@ mod tests {
         curt.curt_clear(test_data);
         let result = curt.get_results();
         assert_eq!(result.len(), 4);
-        assert_eq!(result[0].text, r#"{#Test}"#);
-        assert_eq!(result[1].text, r#"{@more}"#);
-        assert_eq!(result[2].text, r#"{#Test2}"#);
-        assert_eq!(result[3].text, r#"{@TestContent:  more content}"#);
+        assert_eq!(result[0].text, "{tag: Test}");
+        assert_eq!(result[1].text, "{tag: more}");
+        assert_eq!(result[2].text, "{tag: Test2}");
+        assert_eq!(result[3].text, "{TestContent: more content}");
     }
 
     #[test]
@@ -564,6 +1258,209 @@ mod tests {
         assert_eq!(result.len(), 0);
     }
 
+    #[test]
+    fn test_tags_multi_char() {
+        let test_data = &mut "TODO fix, TODOcheck @@me and @@ TOD TODOrelease".to_string();
+        let indicator_lists = vec![Indicators::new(&["TODO", "@@"], IdentRange::Tag)];
+        let mut curt = YogurtYaml::new(&indicator_lists);
+        curt.curt_clear(test_data);
+        let result = curt.get_results();
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0].text, "{tag: check}");
+        assert_eq!(result[1].text, "{tag: me}");
+        assert_eq!(result[2].text, "{tag: release}");
+    }
+
+    #[test]
+    fn test_tags_terminators() {
+        let test_data = &mut "released #v1.2;#v2 and #v3.0\n".to_string();
+        let indicator_lists =
+            vec![Indicators::new(&["#"], IdentRange::Tag).with_terminators(&[' ', ';'])];
+        let mut curt = YogurtYaml::new(&indicator_lists);
+        curt.curt_clear(test_data);
+        let result = curt.get_results();
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0].text, "{tag: v1.2}");
+        assert_eq!(result[1].text, "{tag: v2}");
+        assert_eq!(result[2].text, "{tag: v3.0}");
+    }
+
+    #[test]
+    fn test_tags_context() {
+        let test_data = "## Title\nSee https://x.org/y#section or dev@example.com,\nword#no (#yes) @me\n#Heading\n";
+        let tag_rules = crate::TagRules {
+            punctuation: true,
+            ..crate::TagRules::default()
+        };
+        let indicator_lists =
+            vec![Indicators::new(&["#", "@"], IdentRange::Tag).with_tag_rules(tag_rules)];
+        let mut curt = YogurtYaml::new(&indicator_lists);
+        curt.curt_clear(&mut test_data.to_string());
+        let result = curt.get_results();
+        assert_eq!(result.len(), 3);
+        assert_eq!(result[0].text, "{tag: yes}");
+        assert_eq!(result[1].text, "{tag: me}");
+        assert_eq!(result[2].text, "{tag: Heading}");
+        // Without punctuation only the terminators of `TAG_TERMINATORS` end a tag
+        let indicator_lists = vec![Indicators::new(&["#", "@"], IdentRange::Tag)];
+        let mut curt = YogurtYaml::new(&indicator_lists);
+        curt.curt_clear(&mut test_data.to_string());
+        assert_eq!(curt.get_results()[0].text, "{tag: yes)}");
+    }
+
+    use crate::TagRules;
+    #[test]
+    fn test_tags_skip_headings() {
+        let test_data = &mut "#Heading\n#include <stdio.h>\n  #tag\n".to_string();
+        let tag_rules = TagRules {
+            skip_headings: true,
+            ..TagRules::default()
+        };
+        let indicator_lists =
+            vec![Indicators::new(&["#"], IdentRange::Tag).with_tag_rules(tag_rules)];
+        let mut curt = YogurtYaml::new(&indicator_lists);
+        curt.curt_clear(test_data);
+        let result = curt.get_results();
+        assert_eq!(result.len(), 1);
+        assert_eq!(result[0].text, "{tag: tag}");
+    }
+
+    #[test]
+    fn test_line_endings() {
+        let test_data = "ID[A,\nx: 1] #t\n@k: a\n  b\n\nID[B] @l: c\n";
+        let indicator_lists = vec![
+            Indicators::new(&["ID"], IdentRange::Brackets),
+            Indicators::new(&["#", "@"], IdentRange::Tag),
+        ];
+        let mut expected = Vec::new();
+        for line_break in &["\n", "\r\n", "\r"] {
+            let mut curt = YogurtYaml::new(&indicator_lists);
+            curt.curt_clear(&mut test_data.replace('\n', line_break));
+            let result: Vec<(String, usize, usize, usize, usize)> = curt
+                .get_results()
+                .iter()
+                .map(|result| {
+                    let text = result.get_text().clone();
+                    let (line, column) = (result.get_line(), result.get_column());
+                    (
+                        text,
+                        line,
+                        column,
+                        result.get_end_line(),
+                        result.get_end_column(),
+                    )
+                })
+                .collect();
+            if expected.is_empty() {
+                expected = result;
+            } else {
+                assert_eq!(result, expected);
+            }
+        }
+        assert_eq!(expected.len(), 5);
+        assert_eq!(expected[0], ("{ID: A,\nx: 1}".to_string(), 1, 1, 2, 6));
+        assert_eq!(expected[2], ("{k: a b}".to_string(), 3, 1, 4, 4));
+        assert_eq!(expected[4], ("{l: c}".to_string(), 6, 7, 6, 12));
+    }
+
+    #[test]
+    fn test_words_payload() {
+        let indicator_lists = vec![
+            Indicators::new(&["ID"], IdentRange::Brackets),
+            Indicators::new(&["REF"], IdentRange::Rounds).with_payload(crate::Payload::Words),
+            Indicators::new(&["#"], IdentRange::Tag).with_tag_rules(crate::TagRules {
+                punctuation: true,
+                ..crate::TagRules::default()
+            }),
+        ];
+        let mut curt = YogurtYaml::new(&indicator_lists);
+        curt.curt_clear(&mut "REF(don't see ID[A] #a) ID[B, x: \"(\"]".to_string());
+        curt.nest_annotations();
+        let result: Vec<&str> = curt
+            .get_results()
+            .iter()
+            .map(|result| result.get_text().as_str())
+            .collect();
+        assert_eq!(
+            result,
+            [
+                "{ID: A}",
+                "{REF: \"don't see ID[A] #a\"}",
+                "{tag: a}",
+                "{ID: B, x: \"(\"}"
+            ]
+        );
+        assert_eq!("words".parse(), Ok(crate::Payload::Words));
+        assert_eq!("rounds".parse(), Ok(IdentRange::Rounds));
+    }
+
+    #[test]
+    fn test_extractor() {
+        let indicator_lists = vec![
+            Indicators::new(&["ID", "REF"], IdentRange::Brackets),
+            Indicators::new(&["#"], IdentRange::Tag).with_terminators(&[' ', '\n']),
+        ];
+        let extractor = crate::Extractor::new(&indicator_lists);
+        let test_data = "ID[A] #v1.2 REF[B";
+        let mut curt = YogurtYaml::new(&indicator_lists);
+        curt.curt_clear(&mut test_data.to_string());
+        let expected: Vec<String> = curt
+            .get_results()
+            .iter()
+            .map(|result| result.get_text().clone())
+            .collect();
+        let texts = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..2)
+                .map(|_| scope.spawn(|| extractor.extract(test_data)))
+                .collect();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        for results in texts {
+            let result: Vec<String> = results
+                .iter()
+                .map(|result| result.get_text().clone())
+                .collect();
+            assert_eq!(result, expected);
+            assert_eq!(result, ["{ID: A}", "{tag: v1.2}"]);
+        }
+    }
+
+    #[test]
+    fn test_curt_file() {
+        let directory = crate::test_support::temp_directory("test_curt_file");
+        let first = directory.join("first.adoc");
+        let second = directory.join("second.adoc");
+        std::fs::write(&first, "ID[A]\nID[B, open").unwrap();
+        std::fs::write(&second, "ID[C]").unwrap();
+        let mut curt = YogurtYaml::new_from_str(&["ID"]);
+        assert_eq!(curt.curt_file(&first).unwrap(), "ID[A]\nID[B, open");
+        curt.curt_file(&second).unwrap();
+        let result: Vec<(&str, Option<&str>, usize)> = curt
+            .get_results()
+            .iter()
+            .map(|result| {
+                (
+                    result.get_text().as_str(),
+                    result.get_path(),
+                    result.get_line(),
+                )
+            })
+            .collect();
+        let first = first.to_string_lossy();
+        let second = second.to_string_lossy();
+        assert_eq!(
+            result,
+            [
+                ("{ID: A}", Some(first.as_ref()), 1),
+                ("{ID: C}", Some(second.as_ref()), 1)
+            ]
+        );
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+
     #[test]
     fn test_curt_aggregate() {
         let test_data_part_a =
@@ -0,0 +1,258 @@
+use crate::trace::{self, TraceGraph};
+use crate::{IdentRange, Result};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+
+/// Problem found by a lint pass
+pub struct Diagnostic {
+    rule: String,
+    message: String,
+    suggestions: Vec<String>,
+    path: Option<String>,
+    line: usize,
+    column: usize,
+    end: Option<(usize, usize)>,
+}
+
+impl Diagnostic {
+    pub fn new(rule: &str, message: String, line: usize, column: usize) -> Diagnostic {
+        Diagnostic {
+            rule: rule.to_string(),
+            message,
+            suggestions: Vec::new(),
+            path: None,
+            line,
+            column,
+            end: None,
+        }
+    }
+
+    /// Set the line and column the problem ends at, to report a span
+    pub fn with_end(mut self, line: usize, column: usize) -> Diagnostic {
+        self.end = Some((line, column));
+        self
+    }
+
+    /// Set the path of the file the problem was found in
+    pub fn with_path(mut self, path: Option<&str>) -> Diagnostic {
+        self.path = path.map(|path| path.to_string());
+        self
+    }
+
+    /// return the name of the check, e.g. `dangling-reference`
+    pub fn get_rule(&self) -> &str {
+        &self.rule
+    }
+
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+
+    /// return candidates that might have been meant instead
+    pub fn get_suggestions(&self) -> &[String] {
+        &self.suggestions
+    }
+
+    pub fn get_path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    pub fn get_line(&self) -> usize {
+        self.line
+    }
+
+    pub fn get_column(&self) -> usize {
+        self.column
+    }
+
+    /// return the line and column the problem ends at, if it spans a range
+    pub fn get_end(&self) -> Option<(usize, usize)> {
+        self.end
+    }
+
+    /// return the diagnostic in the form `path:line:column: rule: message`
+    ///
+    /// Problems of other input than files have `<stdin>` as path.
+    /// A span is written as `line:column-end_line:end_column`.
+    pub fn get_print(&self) -> String {
+        let path = self.path.as_deref().unwrap_or("<stdin>");
+        let mut print = format!("{}:{}:{}", path, self.line, self.column);
+        if let Some((line, column)) = self.end {
+            print.push_str(&format!("-{}:{}", line, column));
+        }
+        print.push_str(&format!(": {}: {}", self.rule, self.message));
+        if !self.suggestions.is_empty() {
+            let suggestions: Vec<String> = self
+                .suggestions
+                .iter()
+                .map(|suggestion| format!("`{}`", suggestion))
+                .collect();
+            print.push_str(&format!(", did you mean {}?", suggestions.join(" or ")));
+        }
+        print
+    }
+}
+
+/// Number of single char edits turning `a` into `b`
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, char_a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, char_b) in b.iter().enumerate() {
+            let substitution = diagonal + if char_a == *char_b { 0 } else { 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Finds up to three names close to `name`, closest first
+pub fn suggest<'a>(name: &str, candidates: &[&'a str]) -> Vec<&'a str> {
+    let limit = (name.chars().count() / 3).max(2);
+    let mut close: Vec<(usize, &str)> = candidates
+        .iter()
+        .map(|candidate| (edit_distance(name, candidate), *candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .collect();
+    close.sort();
+    close.dedup();
+    close.into_iter().take(3).map(|(_, name)| name).collect()
+}
+
+// ID[IMPL::dangling_references, implements: REQ::Traceability::dangling_references]
+/// Reports every link pointing at an ID which is declared nowhere
+pub fn dangling_references(graph: &TraceGraph) -> Vec<Diagnostic> {
+    let names: Vec<&str> = graph
+        .get_nodes()
+        .iter()
+        .map(|node| node.get_name())
+        .collect();
+    let declared: HashSet<&str> = names.iter().copied().collect();
+    let mut diagnostics = Vec::new();
+    for link in graph.get_links() {
+        if !declared.contains(link.get_to()) {
+            let mut diagnostic = Diagnostic::new(
+                "dangling-reference",
+                format!(
+                    "`{}` links to `{}` which is not declared",
+                    link.get_kind(),
+                    link.get_to()
+                ),
+                link.get_line(),
+                link.get_column(),
+            )
+            .with_path(link.get_path());
+            diagnostic.suggestions = suggest(link.get_to(), &names)
+                .into_iter()
+                .map(|name| name.to_string())
+                .collect();
+            diagnostics.push(diagnostic);
+        }
+    }
+    diagnostics
+}
+
+/// Reports every name declared more than once by one of the `unique` identifiers, e.g. `ID`
+pub fn duplicate_ids(results: &[Result], unique: &[&str]) -> Vec<Diagnostic> {
+    let mut ordered: Vec<&Result> = results
+        .iter()
+        .filter(|result| result.get_range() != IdentRange::Tag)
+        .filter(|result| unique.contains(&result.get_ident()))
+        .collect();
+    ordered.sort_by(|a, b| a.position().cmp(&b.position()));
+
+    // Declarations in order of their first occurrence, indexed by identifier and name
+    let mut declarations: Vec<(&str, String, Vec<&Result>)> = Vec::new();
+    let mut indices: HashMap<(&str, String), usize> = HashMap::new();
+    for result in ordered {
+        let (name, _) = trace::parse_annotation(result);
+        let ident = result.get_ident();
+        match indices.entry((ident, name.clone())) {
+            Entry::Occupied(index) => declarations[*index.get()].2.push(result),
+            Entry::Vacant(index) => {
+                index.insert(declarations.len());
+                declarations.push((ident, name, vec![result]));
+            }
+        }
+    }
+
+    let mut diagnostics = Vec::new();
+    for (ident, name, found) in declarations {
+        if found.len() > 1 {
+            let locations: Vec<String> = found.iter().map(|result| result.get_location()).collect();
+            diagnostics.push(
+                Diagnostic::new(
+                    "duplicate-id",
+                    format!(
+                        "`{}` declares `{}` {} times, at {}",
+                        ident,
+                        name,
+                        found.len(),
+                        locations.join(", ")
+                    ),
+                    found[0].get_line(),
+                    found[0].get_column(),
+                )
+                .with_path(found[0].get_path()),
+            );
+        }
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lint::{dangling_references, duplicate_ids, edit_distance, suggest};
+    use crate::trace::{TraceConfig, TraceGraph};
+    use crate::{IdentRange, Indicators, YogurtYaml};
+
+    #[test]
+    fn test_suggest() {
+        assert_eq!(edit_distance("REQ::Extract", "REQ::Extarct"), 2);
+        assert_eq!(edit_distance("", "abc"), 3);
+        let candidates = ["REQ::Extract_yaml", "REQ::CLI", "REQ::Extract_json"];
+        assert_eq!(
+            suggest("REQ::Extrct_yaml", &candidates),
+            ["REQ::Extract_yaml", "REQ::Extract_json"]
+        );
+        assert!(suggest("IMPL::Other", &candidates).is_empty());
+    }
+
+    #[test]
+    fn test_dangling_references() {
+        let test_data = "ID[REQ::Multi_Line]\nID[IMPL::A, implements: REQ::Multiline]\nREF[REQ::Multi_Line] REF[TOTALLY_UNKNOWN]";
+        let indicator_lists = vec![Indicators::new(&["ID", "REF"], IdentRange::Brackets)];
+        let mut curt = YogurtYaml::new(&indicator_lists);
+        curt.curt_clear(&mut test_data.to_string());
+        let graph = TraceGraph::new(curt.get_results(), &TraceConfig::default());
+        let diagnostics = dangling_references(&graph);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[0].get_suggestions(), ["REQ::Multi_Line"]);
+        assert_eq!(
+            diagnostics[0].get_print(),
+            "<stdin>:2:1: dangling-reference: `implements` links to `REQ::Multiline` which is not declared, did you mean `REQ::Multi_Line`?"
+        );
+        assert_eq!(diagnostics[1].get_line(), 3);
+        assert_eq!(diagnostics[1].get_column(), 22);
+        assert!(diagnostics[1].get_suggestions().is_empty());
+    }
+
+    #[test]
+    fn test_duplicate_ids() {
+        let test_data = "ID[REQ::A]\nADD[REQ::A, x: 1]\nID[REQ::B] ID[REQ::A, caption: again]\nADD[REQ::A, y: 2]\n  ID[REQ::A]";
+        let indicator_lists = vec![Indicators::new(&["ID", "ADD"], IdentRange::Brackets)];
+        let mut curt = YogurtYaml::new(&indicator_lists);
+        curt.curt_clear(&mut test_data.to_string());
+        let diagnostics = duplicate_ids(curt.get_results(), &["ID"]);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].get_print(),
+            "<stdin>:1:1: duplicate-id: `ID` declares `REQ::A` 3 times, at <stdin>:1:1, <stdin>:3:12, <stdin>:5:3"
+        );
+        let diagnostics = duplicate_ids(curt.get_results(), &["ID", "ADD"]);
+        assert_eq!(diagnostics.len(), 2);
+    }
+}
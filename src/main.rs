@@ -1,93 +1,1075 @@
 extern crate argparse;
 extern crate libcurt;
 
-use argparse::{ArgumentParser, Store};
-use libcurt::{IdentRange, Indicators, YogurtYaml};
-use std::io::{self, Read, Write};
+use argparse::{ArgumentParser, Collect, List, Store, StoreOption, StoreTrue};
+use libcurt::config::Config;
+use libcurt::files;
+use libcurt::lint::{self, Diagnostic};
+use libcurt::merge::{self, Conflict, MergeConfig};
+use libcurt::nesting;
+use libcurt::normalize;
+use libcurt::output::{self, Format};
+use libcurt::query::Query;
+use libcurt::region::RegionConfig;
+use libcurt::schema::Schema;
+use libcurt::suspect::{self, Baseline};
+use libcurt::trace::{CoverageConfig, TraceConfig, TraceGraph};
+use libcurt::{
+    Extractor, IdentRange, Indicators, Payload, Result, TagRules, TagScope, TagStyle, YogurtYaml,
+};
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
 
-/// Uses YogurtYaml to extract yaml from piped data intro standard out
-fn pipe_data(mut curt: YogurtYaml) {
+/// Uses YogurtYaml to extract yaml from all piped data, returns the data
+fn curt_stdin(curt: &mut YogurtYaml) -> String {
     let stdin = io::stdin();
     let mut stdin = stdin.lock();
 
+    let mut data = String::new();
+    stdin.read_to_string(&mut data).unwrap();
+
+    // Extracts everything at once, annotations still open at the end of input are dropped
+    curt.curt_clear(&mut data.clone());
+    data
+}
+
+/// Extracts the results of a file or, without `path`, of standard in
+///
+/// Everything depending on the text of the input is done here.
+/// Returns the results ordered by position and the problems found.
+fn curt_input(
+    extractor: &Extractor,
+    path: Option<&Path>,
+    tag_scope: Option<TagScope>,
+    regions: bool,
+    namespaces: Option<&TraceConfig>,
+) -> (Vec<Result>, Vec<String>) {
+    let mut curt = extractor.curt();
+    let data = match path {
+        Some(path) => match curt.curt_file(path) {
+            Ok(data) => data,
+            // Files which are no text, like images, have nothing to extract
+            Err(error) if error.kind() == io::ErrorKind::InvalidData => {
+                return (Vec::new(), Vec::new())
+            }
+            Err(error) => return (Vec::new(), vec![format!("{}: {}", path.display(), error)]),
+        },
+        None => curt_stdin(&mut curt),
+    };
+    // Tags are aggregated first, as they are found in annotations by their position
+    if let Some(scope) = tag_scope {
+        curt.aggregate_tags(scope);
+    }
+    if let Some(config) = namespaces {
+        curt.resolve_namespaces(config);
+    }
+    let mut problems = Vec::new();
+    if regions {
+        for diagnostic in curt.capture_regions(&data, &RegionConfig::default()) {
+            problems.push(diagnostic.get_print());
+        }
+    }
+    let mut results = curt.get_results().clone();
+    results.sort_by_key(|result| (result.get_line(), result.get_column()));
+    (results, problems)
+}
+
+/// Extracts `files` on `jobs` threads, the results keep the order of `files`
+fn curt_files(
+    extractor: &Extractor,
+    files: &[PathBuf],
+    tag_scope: Option<TagScope>,
+    regions: bool,
+    namespaces: Option<&TraceConfig>,
+    jobs: usize,
+) -> Vec<(Vec<Result>, Vec<String>)> {
+    let next = AtomicUsize::new(0);
+    let mut found: Vec<(Vec<Result>, Vec<String>)> = vec![(Vec::new(), Vec::new()); files.len()];
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..jobs.clamp(1, files.len().max(1)))
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        if index >= files.len() {
+                            return done;
+                        }
+                        let path = Some(files[index].as_path());
+                        let output = curt_input(extractor, path, tag_scope, regions, namespaces);
+                        done.push((index, output));
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            for (index, output) in worker.join().unwrap() {
+                found[index] = output;
+            }
+        }
+    });
+    found
+}
+
+/// Writes results as the format of `options` into standard out,
+/// grouped by name or only the fields of `--select` if asked for
+fn write_results(results: &[Result], options: &Options) {
     let stdout = io::stdout();
     let mut handle = io::BufWriter::new(stdout);
+    let written = if options.by_name {
+        output::write_grouped(&mut handle, results, &options.trace, options.format())
+    } else if options.select.is_some() {
+        output::write_selected(&mut handle, results, &options.fields(), options.format())
+    } else {
+        output::write_results(&mut handle, results, options.format(), options.metadata)
+    };
+    if let Err(error) = written {
+        eprintln!("{}", error);
+        process::exit(2);
+    }
+}
 
-    let mut line = String::new();
+/// Writes the requirement coverage of the results into standard out
+fn write_coverage(results: &[Result], trace: &TraceConfig, prefix: String) {
+    let graph = TraceGraph::new(results, trace);
+    let config = CoverageConfig {
+        prefix,
+        ..CoverageConfig::default()
+    };
+    print!("{}", graph.coverage(&config).get_print());
+}
+
+/// Writes all diagnostics of the lint passes into standard out and returns whether there were any
+fn write_lints(results: &[Result], trace: &TraceConfig, unique: &[&str]) -> bool {
+    let graph = TraceGraph::new(results, trace);
+    let mut diagnostics = lint::dangling_references(&graph);
+    diagnostics.extend(lint::duplicate_ids(results, unique));
+    for diagnostic in &diagnostics {
+        println!("{}", diagnostic.get_print());
+    }
+    !diagnostics.is_empty()
+}
+
+/// Writes the suspect links since `baseline` into standard out and returns whether there were any
+fn write_suspects(options: &Options, baseline: &str) -> bool {
+    let baseline = read_baseline(baseline);
+    let graph = TraceGraph::new(&options.extract_fingerprinted(), &options.trace);
+    let diagnostics = suspect::suspect_links(&graph, &baseline);
+    for diagnostic in &diagnostics {
+        println!("{}", diagnostic.get_print());
+    }
+    !diagnostics.is_empty()
+}
+
+/// Writes the fingerprints of all linked nodes into `baseline`, marking the links as reviewed
+///
+/// The reviews already in `baseline` are kept, unless the same links are reviewed again.
+fn write_review(options: &Options, baseline: &str) {
+    let mut reviewed = if Path::new(baseline).exists() {
+        read_baseline(baseline)
+    } else {
+        Baseline::default()
+    };
+    reviewed.review(&TraceGraph::new(
+        &options.extract_fingerprinted(),
+        &options.trace,
+    ));
+    if let Err(error) = fs::write(baseline, reviewed.get_print()) {
+        eprintln!("{}: {}", baseline, error);
+        process::exit(2);
+    }
+}
 
-    while let Ok(n_bytes) = stdin.read_to_string(&mut line) {
-        if n_bytes == 0 {
-            line.push('\n');
+/// Reads the baseline at `path`, exits if it can not be read
+fn read_baseline(path: &str) -> Baseline {
+    match fs::read_to_string(path)
+        .map_err(|error| error.to_string())
+        .and_then(|text| text.parse())
+    {
+        Ok(baseline) => baseline,
+        Err(error) => {
+            eprintln!("{}: {}", path, error);
+            process::exit(2);
         }
+    }
+}
 
-        curt.curt(&line);
+/// Reads the configuration at `path` or else the nearest one above the working directory
+fn read_config(path: Option<String>) -> Config {
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => match env::current_dir()
+            .ok()
+            .and_then(|directory| Config::find(&directory))
+        {
+            Some(path) => path,
+            None => return Config::default(),
+        },
+    };
+    match Config::load(&path) {
+        Ok(config) => config,
+        Err(error) => {
+            eprintln!("{}: {}", path.display(), error);
+            process::exit(2);
+        }
+    }
+}
 
-        if !curt.reset_open() {
-            for result in curt.get_results() {
-                writeln!(handle, "- {}", result.get_text()).unwrap();
-            }
-            curt.clear_results();
-            line.clear();
+/// Reads an indicator like `IDENT:range` or `IDENT:range:payload`
+fn parse_indicator(spec: &str) -> std::result::Result<(&str, IdentRange, Payload), String> {
+    let mut parts = spec.splitn(3, ':');
+    let ident = parts.next().unwrap_or_default();
+    if ident.is_empty() {
+        return Err(format!("indicator `{}` has no identifier", spec));
+    }
+    let range = match parts.next() {
+        Some(range) => range.parse()?,
+        None => {
+            return Err(format!(
+                "indicator `{}` has no range like `:brackets`",
+                spec
+            ))
         }
+    };
+    let payload = match parts.next() {
+        Some(payload) => payload.parse()?,
+        None => Payload::Yaml,
+    };
+    Ok((ident, range, payload))
+}
+
+/// Takes the words given on the command line, or else the ones of the configuration
+fn or_config(given: String, config: &[String]) -> String {
+    if given.trim().is_empty() {
+        config.join(" ")
+    } else {
+        given
+    }
+}
+
+/// Commands of curt-extract, `extract` if none is given
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Command {
+    Extract,
+    Lint,
+    Trace,
+    Query,
+    Fmt,
+}
+
+impl FromStr for Command {
+    type Err = String;
 
-        if n_bytes == 0 {
-            break;
+    fn from_str(s: &str) -> std::result::Result<Command, String> {
+        match s {
+            "extract" => Ok(Command::Extract),
+            "lint" => Ok(Command::Lint),
+            "trace" => Ok(Command::Trace),
+            "query" => Ok(Command::Query),
+            "fmt" => Ok(Command::Fmt),
+            _ => Err(format!("unknown command `{}`", s)),
         }
     }
 }
 
-/// main function of curt-extract
-fn main() {
-    let mut brackets = String::new();
-    let mut closures = String::new();
-    let mut crickets = String::new();
-    let mut rounds = String::new();
-    let mut tags = String::new();
-    {
-        let mut ap = ArgumentParser::new();
-        ap.set_description("Extract yaml from text via pipe e.g. `cat file | curt-extract -b ID`");
-        ap.refer(&mut tags).add_option(
+/// Options shared by all commands: configuration, input paths, output format and extraction
+struct Options {
+    config: Option<String>,
+    paths: Vec<String>,
+    format: Option<Format>,
+    metadata: bool,
+    by_name: bool,
+    filter: Option<String>,
+    query: Option<Query>,
+    select: Option<String>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    no_ignore: bool,
+    jobs: usize,
+    tags: String,
+    brackets: String,
+    closures: String,
+    crickets: String,
+    rounds: String,
+    indicator: Vec<String>,
+    skip_headings: bool,
+    tag_style: TagStyle,
+    tag_scope: Option<TagScope>,
+    nest: bool,
+    regions: bool,
+    merge: Option<Conflict>,
+    no_namespaces: bool,
+    schema: Option<Schema>,
+    trace: TraceConfig,
+}
+
+impl Options {
+    fn new() -> Options {
+        Options {
+            config: None,
+            paths: Vec::new(),
+            format: None,
+            metadata: false,
+            by_name: false,
+            filter: None,
+            query: None,
+            select: None,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            no_ignore: false,
+            jobs: 0,
+            tags: String::new(),
+            brackets: String::new(),
+            closures: String::new(),
+            crickets: String::new(),
+            rounds: String::new(),
+            indicator: Vec::new(),
+            skip_headings: false,
+            tag_style: TagStyle::Named,
+            tag_scope: None,
+            nest: false,
+            regions: false,
+            merge: None,
+            no_namespaces: false,
+            schema: None,
+            trace: TraceConfig::default(),
+        }
+    }
+
+    /// Adds the options to `ap`, so each command reads them the same way
+    fn refer<'a>(&'a mut self, ap: &mut ArgumentParser<'a>) {
+        ap.refer(&mut self.config).add_option(
+            &["--config"],
+            StoreOption,
+            "Read settings from this file instead of the nearest `.curt.yaml` or `curt.toml`",
+        );
+        ap.refer(&mut self.format).add_option(
+            &["--format"],
+            StoreOption,
+            "Write the results as `yaml` list, the default, as `yaml-block`, `json`, `jsonl`, `csv` or `grep`",
+        );
+        ap.refer(&mut self.metadata).add_option(
+            &["--metadata"],
+            StoreTrue,
+            "Write the path, line, column, identifier and range along with each result",
+        );
+        ap.refer(&mut self.by_name).add_option(
+            &["--by-name"],
+            StoreTrue,
+            "Write a single mapping from each ID name to its attributes, references and tags",
+        );
+        ap.refer(&mut self.filter).add_option(
+            &["--where"],
+            StoreOption,
+            "Keep only results matching e.g. `ident == \"ID\" && (status == \"open\" || has(tests))`",
+        );
+        ap.refer(&mut self.select).add_option(
+            &["--select"],
+            StoreOption,
+            "Write only these fields of each result, e.g. `name,caption,path,line`",
+        );
+        ap.refer(&mut self.paths).add_argument(
+            "paths",
+            List,
+            "Files and directories to read recursively, standard in if there are none",
+        );
+        ap.refer(&mut self.include).add_option(
+            &["--include"],
+            Collect,
+            "Only read files matching a glob like `*.adoc`, relative to each directory",
+        );
+        ap.refer(&mut self.exclude).add_option(
+            &["--exclude"],
+            Collect,
+            "Skip files and directories matching a glob like `target`",
+        );
+        ap.refer(&mut self.no_ignore).add_option(
+            &["--no-ignore"],
+            StoreTrue,
+            "Read files even if they are ignored by `.gitignore` or `.curtignore`",
+        );
+        ap.refer(&mut self.jobs).add_option(
+            &["--jobs", "-j"],
+            Store,
+            "Number of files extracted in parallel, by default one per cpu",
+        );
+        ap.refer(&mut self.tags).add_option(
             &["--tags", "-t"],
             Store,
-            "Get tags defined by an single char identifier e.g. `#` or `@`",
+            "Get tags defined by identifiers of one or more chars, e.g. `# @` or `TODO`",
+        );
+        ap.refer(&mut self.skip_headings).add_option(
+            &["--skip-headings"],
+            StoreTrue,
+            "Skip tags at the start of a line, e.g. `#Title` or `#include`",
+        );
+        ap.refer(&mut self.tag_style).add_option(
+            &["--tag-style"],
+            Store,
+            "Write tags as `named` e.g. `{tag: Test}`, as `flag` e.g. `{Test: true}` or `raw`",
         );
-        ap.refer(&mut brackets).add_option(
+        ap.refer(&mut self.tag_scope).add_option(
+            &["--tag-scope"],
+            StoreOption,
+            "Collect tags per `line`, `paragraph` or `annotation` into `{tags: [...]}`",
+        );
+        ap.refer(&mut self.nest).add_option(
+            &["--nest"],
+            StoreTrue,
+            "Move annotations and tags within another annotation into it, like `ID[A, depends: REF[B]]`",
+        );
+        ap.refer(&mut self.regions).add_option(
+            &["--regions"],
+            StoreTrue,
+            "Capture the text of `ID[X, region: true] ... END[X]` as attribute `region` of `X`",
+        );
+        ap.refer(&mut self.merge).add_option(
+            &["--merge"],
+            StoreOption,
+            "Merge `ADD[NAME, ...]` into `ID[NAME, ...]`, on conflicts `error`, `override` or `append`",
+        );
+        ap.refer(&mut self.no_namespaces).add_option(
+            &["--no-namespaces"],
+            StoreTrue,
+            "Keep relative names like `ID[::name]` instead of resolving them against `ID[REQ::A]`",
+        );
+        ap.refer(&mut self.brackets).add_option(
             &["--brackets", "-b"],
             Store,
             "Get yaml defined by an identifier and enclosed by brackets: `IDENT[.*]`",
         );
-        ap.refer(&mut closures).add_option(
+        ap.refer(&mut self.closures).add_option(
             &["--closures", "-c"],
             Store,
             "Get words defined by an identifier and enclosed by closures: `IDENT{.*}`",
         );
-        ap.refer(&mut crickets).add_option(
+        ap.refer(&mut self.crickets).add_option(
             &["--crickets", "-i"],
             Store,
             "Get yaml defined by an identifier and enclosed by crickets: `IDENT<.*>`",
         );
-        ap.refer(&mut rounds).add_option(
+        ap.refer(&mut self.rounds).add_option(
             &["--rounds", "-r"],
             Store,
             "Get words defined by an identifier and enclosed by rounds: `IDENT(.*)`",
         );
-        ap.parse_args_or_exit();
-    }
-    let idents = tags.split_whitespace().collect::<Vec<&str>>();
-    let tags_indicators = Indicators::new(&idents, IdentRange::Tag);
-    let idents = brackets.split_whitespace().collect::<Vec<&str>>();
-    let brackets_indicators = Indicators::new(&idents, IdentRange::Brackets);
-    let idents = closures.split_whitespace().collect::<Vec<&str>>();
-    let closures_indicators = Indicators::new(&idents, IdentRange::Closures);
-    let idents = brackets.split_whitespace().collect::<Vec<&str>>();
-    let crickets_indicators = Indicators::new(&idents, IdentRange::Crickets);
-    let idents = closures.split_whitespace().collect::<Vec<&str>>();
-    let rounds_indicators = Indicators::new(&idents, IdentRange::Rounds);
-    let mut indicators = Vec::new();
-    indicators.push(tags_indicators);
-    indicators.push(brackets_indicators);
-    indicators.push(closures_indicators);
-    indicators.push(crickets_indicators);
-    indicators.push(rounds_indicators);
-    pipe_data(YogurtYaml::new(&indicators));
+        ap.refer(&mut self.indicator).add_option(
+            &["--indicator"],
+            Collect,
+            "Get an identifier with any range, e.g. `REF:rounds` or `NOTE:closures:words`",
+        );
+    }
+
+    /// Reads the configuration and takes its settings where none were given on the command line
+    fn load_config(&mut self) -> Config {
+        let config = read_config(self.config.clone());
+        self.tags = or_config(std::mem::take(&mut self.tags), &config.tags);
+        self.brackets = or_config(std::mem::take(&mut self.brackets), &config.brackets);
+        self.closures = or_config(std::mem::take(&mut self.closures), &config.closures);
+        self.crickets = or_config(std::mem::take(&mut self.crickets), &config.crickets);
+        self.rounds = or_config(std::mem::take(&mut self.rounds), &config.rounds);
+        self.trace = trace_config(&config);
+        if self.include.is_empty() {
+            self.include = config.include.clone();
+        }
+        if self.exclude.is_empty() {
+            self.exclude = config.exclude.clone();
+        }
+        if let (None, Some(format)) = (self.format, &config.format) {
+            match format.parse() {
+                Ok(format) => self.format = Some(format),
+                Err(error) => {
+                    eprintln!("{}", error);
+                    process::exit(2);
+                }
+            }
+        }
+        // Checked before extracting, so a mistake does not wait for the whole tree
+        let shapes = [
+            ("--by-name", self.by_name),
+            ("--select", self.select.is_some()),
+            ("--metadata", self.metadata),
+        ];
+        let given: Vec<&str> = shapes
+            .iter()
+            .filter(|(_, given)| *given)
+            .map(|(option, _)| *option)
+            .collect();
+        if given.len() > 1 {
+            eprintln!("{} can not be combined with {}", given[1], given[0]);
+            process::exit(2);
+        }
+        if let Some(filter) = &self.filter {
+            match filter.parse() {
+                Ok(query) => self.query = Some(query),
+                Err(error) => {
+                    eprintln!("--where: {}", error);
+                    process::exit(2);
+                }
+            }
+        }
+        config
+    }
+
+    /// Exits if `--where` was given to `command`, which checks all results at once
+    fn reject_filter(&self, command: &str) {
+        if self.filter.is_some() {
+            eprintln!("--where can not be combined with {}", command);
+            process::exit(2);
+        }
+    }
+
+    /// The fields of `--select` in the order given, each field once
+    fn fields(&self) -> Vec<String> {
+        let mut fields: Vec<String> = Vec::new();
+        for field in self.select.iter().flat_map(|select| select.split(',')) {
+            let field = field.trim();
+            if !field.is_empty() && !fields.iter().any(|other| other == field) {
+                fields.push(field.to_string());
+            }
+        }
+        fields
+    }
+
+    /// The format to write results in, `yaml` if none is given
+    fn format(&self) -> Format {
+        self.format.unwrap_or(Format::Yaml)
+    }
+
+    /// Compiles the indicators of all identifiers to extract
+    fn extractor(&self) -> std::result::Result<Extractor, String> {
+        // Tags within markdown and code end at punctuation like `(see #a)`
+        let tag_rules = TagRules {
+            skip_headings: self.skip_headings,
+            punctuation: true,
+            ..TagRules::default()
+        };
+        let idents = self.tags.split_whitespace().collect::<Vec<&str>>();
+        let tags_indicators = Indicators::new(&idents, IdentRange::Tag)
+            .with_tag_rules(tag_rules)
+            .with_tag_style(self.tag_style);
+        let idents = self.brackets.split_whitespace().collect::<Vec<&str>>();
+        let brackets_indicators = Indicators::new(&idents, IdentRange::Brackets);
+        let idents = self.closures.split_whitespace().collect::<Vec<&str>>();
+        let closures_indicators = Indicators::new(&idents, IdentRange::Closures);
+        let idents = self.crickets.split_whitespace().collect::<Vec<&str>>();
+        let crickets_indicators = Indicators::new(&idents, IdentRange::Crickets);
+        let idents = self.rounds.split_whitespace().collect::<Vec<&str>>();
+        let rounds_indicators = Indicators::new(&idents, IdentRange::Rounds);
+        let mut indicators = vec![
+            tags_indicators,
+            brackets_indicators,
+            closures_indicators,
+            crickets_indicators,
+            rounds_indicators,
+        ];
+        // Identifiers of `--indicator` sharing range and payload are scanned as one list
+        let mut groups: Vec<(IdentRange, Payload, Vec<&str>)> = Vec::new();
+        for spec in &self.indicator {
+            let (ident, range, payload) = parse_indicator(spec)?;
+            match groups
+                .iter_mut()
+                .find(|(other, kind, _)| *other == range && *kind == payload)
+            {
+                Some((_, _, idents)) => idents.push(ident),
+                None => groups.push((range, payload, vec![ident])),
+            }
+        }
+        for (range, payload, idents) in &groups {
+            indicators.push(
+                Indicators::new(idents, *range)
+                    .with_payload(*payload)
+                    .with_tag_rules(tag_rules)
+                    .with_tag_style(self.tag_style),
+            );
+        }
+        Ok(Extractor::new(&indicators))
+    }
+
+    /// Compiles the indicators of all identifiers to extract, exits if an `--indicator` is invalid
+    fn extractor_or_exit(&self) -> Extractor {
+        match self.extractor() {
+            Ok(extractor) => extractor,
+            Err(error) => {
+                eprintln!("{}", error);
+                process::exit(2);
+            }
+        }
+    }
+
+    /// Lists the files to read, empty if standard in is read
+    fn files(&self) -> Vec<PathBuf> {
+        let found = files::find_files(&self.paths, &self.include, &self.exclude, !self.no_ignore);
+        match found {
+            Ok((files, problems)) => {
+                for problem in problems {
+                    eprintln!("{}", problem);
+                }
+                files
+            }
+            Err(error) => {
+                eprintln!("{}", error);
+                process::exit(2);
+            }
+        }
+    }
+
+    /// Extracts the results of all inputs, the problems found are written into standard error
+    ///
+    /// Returns the results matching `--where` and whether there were problems.
+    fn extract(&self) -> (Vec<Result>, bool) {
+        let (results, problems) = self.extract_as(self.tag_scope, self.nest, self.regions);
+        for problem in &problems {
+            eprintln!("{}", problem);
+        }
+        (results, !problems.is_empty())
+    }
+
+    /// Extracts the results to fingerprint for `--review` and `--suspects`
+    ///
+    /// Fingerprints must not change with `--regions`, `--tag-scope` or `--nest`, so regions
+    /// are always captured, tags are not aggregated and annotations are always nested.
+    /// The problems are the ones `extract` already reported, so they are left out.
+    fn extract_fingerprinted(&self) -> Vec<Result> {
+        self.extract_as(None, true, true).0
+    }
+
+    /// Extracts the results of all inputs, returns the ones matching `--where` and the problems
+    fn extract_as(
+        &self,
+        tag_scope: Option<TagScope>,
+        nest: bool,
+        regions: bool,
+    ) -> (Vec<Result>, Vec<String>) {
+        let extractor = self.extractor_or_exit();
+        let jobs = match self.jobs {
+            0 => thread::available_parallelism().map_or(1, |jobs| jobs.get()),
+            jobs => jobs,
+        };
+        let namespaces = (!self.no_namespaces).then_some(&self.trace);
+        let outputs = if self.paths.is_empty() {
+            vec![curt_input(&extractor, None, tag_scope, regions, namespaces)]
+        } else {
+            let files = self.files();
+            curt_files(&extractor, &files, tag_scope, regions, namespaces, jobs)
+        };
+        let mut results = Vec::new();
+        let mut problems = Vec::new();
+        for (found, found_problems) in outputs {
+            results.extend(found);
+            problems.extend(found_problems);
+        }
+        let (results, diagnostics) = self.combine(results, nest);
+        problems.extend(diagnostics.iter().map(Diagnostic::get_print));
+        (results, problems)
+    }
+
+    /// Nests and merges the results of all inputs, validates them and keeps the ones of `--where`
+    ///
+    /// The schema sees the attributes as written before nesting, with the ones of `ADD` merged in.
+    fn combine(&self, mut results: Vec<Result>, nest: bool) -> (Vec<Result>, Vec<Diagnostic>) {
+        let mut diagnostics = Vec::new();
+        let merge_config = self.merge.map(|conflict| MergeConfig {
+            conflict,
+            ..MergeConfig::default()
+        });
+        if let Some(schema) = &self.schema {
+            let mut written = nesting::quote_annotations(results.clone());
+            if let Some(config) = &merge_config {
+                written = merge::merge_additions(written, config).0;
+            }
+            diagnostics.extend(schema.validate(&written));
+        }
+        if nest {
+            results = nesting::nest_annotations(results);
+        }
+        if let Some(config) = &merge_config {
+            let (merged, merge_diagnostics) = merge::merge_additions(results, config);
+            results = merged;
+            diagnostics.extend(merge_diagnostics);
+        }
+        if let Some(query) = &self.query {
+            results.retain(|result| query.matches(result));
+        }
+        (results, diagnostics)
+    }
+}
+
+/// Parses `args` of a command, exits on errors or after writing the help
+fn parse_or_exit(ap: &ArgumentParser, args: Vec<String>) {
+    if let Err(code) = ap.parse(args, &mut io::stdout(), &mut io::stderr()) {
+        process::exit(code);
+    }
+}
+
+/// Reads the schema at `path`, exits if it can not be read
+fn read_schema(path: &str) -> Schema {
+    match fs::read_to_string(path)
+        .map_err(|error| error.to_string())
+        .and_then(|text| text.parse())
+    {
+        Ok(schema) => schema,
+        Err(error) => {
+            eprintln!("{}: {}", path, error);
+            process::exit(2);
+        }
+    }
+}
+
+/// Link attributes of the configuration, or else the default ones
+fn trace_config(config: &Config) -> TraceConfig {
+    match &config.links {
+        Some(links) => TraceConfig {
+            links: links.clone(),
+            ..TraceConfig::default()
+        },
+        None => TraceConfig::default(),
+    }
+}
+
+/// Identifiers given by `--unique`, or else the ones of the configuration or `ID`
+fn unique_idents(unique: Option<String>, config: &Config) -> Vec<String> {
+    match unique {
+        Some(unique) => unique.split_whitespace().map(String::from).collect(),
+        None => config
+            .unique
+            .clone()
+            .unwrap_or_else(|| vec!["ID".to_string()]),
+    }
+}
+
+/// Exits if a path of `extract` is a command name given after the options, like `lint` in
+/// `curt-extract -b ID lint docs`, unless there is such a file
+fn reject_misplaced_command(paths: &[String]) {
+    let misplaced = paths
+        .iter()
+        .find(|path| path.parse::<Command>().is_ok() && !Path::new(path).exists());
+    if let Some(command) = misplaced {
+        eprintln!(
+            "`{}` is a command and has to be the first argument, e.g. `curt-extract {} -b ID docs`",
+            command, command
+        );
+        process::exit(2);
+    }
+}
+
+/// `curt-extract extract`, also run if no command is given
+fn run_extract(args: Vec<String>) {
+    let mut options = Options::new();
+    let mut schema: Option<String> = None;
+    {
+        let mut ap = ArgumentParser::new();
+        ap.set_description(
+            "Extract yaml from files, directories or a pipe e.g. `curt-extract -b ID docs src`. \
+             Other commands are `lint`, `trace`, `query` and `fmt`, which have to come first, \
+             see `curt-extract COMMAND --help`",
+        );
+        options.refer(&mut ap);
+        ap.refer(&mut schema).add_option(
+            &["--schema"],
+            StoreOption,
+            "Validate the attributes of each identifier against the rules of a yaml file",
+        );
+        parse_or_exit(&ap, args);
+    }
+    reject_misplaced_command(&options.paths);
+    let config = options.load_config();
+    options.schema = schema
+        .or_else(|| config.schema.clone())
+        .map(|path| read_schema(&path));
+    let (results, failed) = options.extract();
+    write_results(&results, &options);
+    if failed {
+        process::exit(1);
+    }
+}
+
+/// `curt-extract lint`, writes the lints into standard out and fails if there are any
+///
+/// Schema violations are found while extracting and written into standard error like other problems.
+fn run_lint(args: Vec<String>) {
+    let mut options = Options::new();
+    let mut schema: Option<String> = None;
+    let mut suspects: Option<String> = None;
+    let mut unique: Option<String> = None;
+    {
+        let mut ap = ArgumentParser::new();
+        ap.set_description(
+            "Report references to undeclared IDs, duplicates, schema violations and suspect links",
+        );
+        options.refer(&mut ap);
+        ap.refer(&mut schema).add_option(
+            &["--schema"],
+            StoreOption,
+            "Validate the attributes of each identifier against the rules of a yaml file",
+        );
+        ap.refer(&mut suspects).add_option(
+            &["--suspects"],
+            StoreOption,
+            "Report links whose target changed since they were reviewed into a baseline file",
+        );
+        ap.refer(&mut unique).add_option(
+            &["--unique"],
+            StoreOption,
+            "Identifiers whose names may only be declared once, by default `ID`",
+        );
+        parse_or_exit(&ap, args);
+    }
+    let config = options.load_config();
+    options.reject_filter("lint");
+    options.schema = schema
+        .or_else(|| config.schema.clone())
+        .map(|path| read_schema(&path));
+    let unique = unique_idents(unique, &config);
+    let (results, mut failed) = options.extract();
+    let unique = unique.iter().map(String::as_str).collect::<Vec<&str>>();
+    failed |= write_lints(&results, &options.trace, &unique);
+    if let Some(baseline) = suspects {
+        failed |= write_suspects(&options, &baseline);
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+/// `curt-extract trace`, writes the trace graph, the coverage or a review baseline
+fn run_trace(args: Vec<String>) {
+    let mut options = Options::new();
+    let mut coverage: Option<String> = None;
+    let mut review: Option<String> = None;
+    {
+        let mut ap = ArgumentParser::new();
+        ap.set_description(
+            "Write the graph of IDs and their links in the dot language of graphviz",
+        );
+        options.refer(&mut ap);
+        ap.refer(&mut coverage).add_option(
+            &["--coverage"],
+            StoreOption,
+            "Report requirements starting with e.g. `REQ::` that are not implemented or tested",
+        );
+        ap.refer(&mut review).add_option(
+            &["--review"],
+            StoreOption,
+            "Update the fingerprints of all link targets in a baseline file, keeping other reviews",
+        );
+        parse_or_exit(&ap, args);
+    }
+    options.load_config();
+    let (results, failed) = options.extract();
+    if let Some(baseline) = review {
+        write_review(&options, &baseline);
+    } else if let Some(prefix) = coverage {
+        write_coverage(&results, &options.trace, prefix);
+    } else {
+        print!("{}", TraceGraph::new(&results, &options.trace).get_dot());
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+/// `curt-extract query`, writes only the results matching all filters
+fn run_query(args: Vec<String>) {
+    let mut options = Options::new();
+    let mut idents = String::new();
+    let mut name: Option<String> = None;
+    {
+        let mut ap = ArgumentParser::new();
+        ap.set_description("Write only the results matching all given filters");
+        options.refer(&mut ap);
+        ap.refer(&mut idents).add_option(
+            &["--ident"],
+            Store,
+            "Keep results of one of these identifiers, e.g. `ID REF`",
+        );
+        ap.refer(&mut name).add_option(
+            &["--name"],
+            StoreOption,
+            "Keep annotations whose name starts with e.g. `REQ::`",
+        );
+        parse_or_exit(&ap, args);
+    }
+    options.load_config();
+    let idents = idents.split_whitespace().collect::<Vec<&str>>();
+    let (results, failed) = options.extract();
+    let results: Vec<Result> = results
+        .into_iter()
+        .filter(|result| idents.is_empty() || idents.contains(&result.get_ident()))
+        .filter(|result| {
+            name.as_ref()
+                .is_none_or(|name| result.get_name().starts_with(name.as_str()))
+        })
+        .collect();
+    write_results(&results, &options);
+    if failed {
+        process::exit(1);
+    }
+}
+
+/// `curt-extract fmt`, rewrites the annotations of files in normalized flow yaml
+fn run_fmt(args: Vec<String>) {
+    let mut options = Options::new();
+    let mut check = false;
+    {
+        let mut ap = ArgumentParser::new();
+        ap.set_description(
+            "Normalize annotations like `ID[A,x:  'y']` into `ID[A, x: y]` within the files, \
+             or write standard in normalized into standard out",
+        );
+        options.refer(&mut ap);
+        ap.refer(&mut check).add_option(
+            &["--check"],
+            StoreTrue,
+            "Write the files that are not normalized instead of changing them and fail if there are any",
+        );
+        parse_or_exit(&ap, args);
+    }
+    options.load_config();
+    options.reject_filter("fmt");
+    let extractor = options.extractor_or_exit();
+    if options.paths.is_empty() {
+        let mut curt = extractor.curt();
+        let data = curt_stdin(&mut curt);
+        let normalized = normalize::normalize_annotations(&data, curt.get_results());
+        print!("{}", normalized);
+        if check && normalized != data {
+            process::exit(1);
+        }
+        return;
+    }
+    let mut failed = false;
+    for path in options.files() {
+        let mut curt = extractor.curt();
+        let data = match curt.curt_file(&path) {
+            Ok(data) => data,
+            Err(error) if error.kind() == io::ErrorKind::InvalidData => continue,
+            Err(error) => {
+                eprintln!("{}: {}", path.display(), error);
+                failed = true;
+                continue;
+            }
+        };
+        let normalized = normalize::normalize_annotations(&data, curt.get_results());
+        if normalized == data {
+            continue;
+        }
+        if check {
+            println!("{}", path.display());
+            failed = true;
+        } else if let Err(error) = fs::write(&path, normalized) {
+            eprintln!("{}: {}", path.display(), error);
+            failed = true;
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+/// main function of curt-extract
+fn main() {
+    let mut args: Vec<String> = env::args().collect();
+    let command = match args.get(1).map(|arg| arg.parse()) {
+        Some(Ok(command)) => {
+            let name = args.remove(1);
+            args[0] = format!("{} {}", args[0], name);
+            command
+        }
+        // Without a command the arguments are the ones of `extract`
+        _ => Command::Extract,
+    };
+    match command {
+        Command::Extract => run_extract(args),
+        Command::Lint => run_lint(args),
+        Command::Trace => run_trace(args),
+        Command::Query => run_query(args),
+        Command::Fmt => run_fmt(args),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Options;
+    use libcurt::merge::Conflict;
+    use libcurt::IdentRange;
+
+    fn extract(options: &Options, test_data: &str) -> Vec<(String, IdentRange)> {
+        let extractor = options.extractor().unwrap();
+        let mut curt = extractor.curt();
+        curt.curt_clear(&mut test_data.to_string());
+        curt.get_results()
+            .iter()
+            .map(|result| (result.get_text().clone(), result.get_range()))
+            .collect()
+    }
+
+    #[test]
+    fn test_extractor() {
+        let mut options = Options::new();
+        options.tags = "#".to_string();
+        options.brackets = "B".to_string();
+        options.closures = "K".to_string();
+        options.crickets = "C".to_string();
+        options.rounds = "R".to_string();
+        options.indicator = vec!["W:rounds:words".to_string(), "V:brackets".to_string()];
+        let found = extract(&options, "B[b] K{k} C<c> R(r) W(w x) V[v] B<x> C[x] #t");
+        let expected = [
+            ("{B: b}", IdentRange::Brackets),
+            ("{K: k}", IdentRange::Closures),
+            ("{C: c}", IdentRange::Crickets),
+            ("{R: r}", IdentRange::Rounds),
+            ("{W: w x}", IdentRange::Rounds),
+            ("{V: v}", IdentRange::Brackets),
+            ("{tag: t}", IdentRange::Tag),
+        ];
+        let expected: Vec<(String, IdentRange)> = expected
+            .iter()
+            .map(|(text, range)| (text.to_string(), *range))
+            .collect();
+        assert_eq!(found, expected);
+
+        options.indicator = vec!["W:squares".to_string()];
+        assert!(options.extractor().is_err());
+    }
+
+    #[test]
+    fn test_combine() {
+        let mut options = Options::new();
+        options.brackets = "ID REF ADD".to_string();
+        options.merge = Some(Conflict::Append);
+        options.nest = true;
+        options.schema = Some(
+            "ID:\n  caption: {type: string, required: true}\n  depends: {items: string}"
+                .parse()
+                .unwrap(),
+        );
+        let test_data = "ID[X, depends: [REF[Y]]] ADD[X, caption: c]\nID[Y]";
+        let extractor = options.extractor().unwrap();
+        let mut curt = extractor.curt();
+        curt.curt_clear(&mut test_data.to_string());
+        let (results, diagnostics) = options.combine(curt.get_results().clone(), true);
+        let texts: Vec<&str> = results
+            .iter()
+            .map(|result| result.get_text().as_str())
+            .collect();
+        assert_eq!(
+            texts,
+            ["{ID: X, depends: [{REF: Y}], caption: c}", "{ID: Y}"]
+        );
+        let diagnostics: Vec<String> = diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.get_print())
+            .collect();
+        assert_eq!(
+            diagnostics,
+            ["<stdin>:2:1-2:6: schema-required: `ID` `Y` misses the required attribute `caption`"]
+        );
+    }
 }
@@ -0,0 +1,255 @@
+use crate::flow::to_flow;
+use crate::lint::Diagnostic;
+use crate::trace::{parse_annotation, write_annotation};
+use crate::{IdentRange, Result};
+use std::collections::HashMap;
+use std::str::FromStr;
+use yaml_rust::Yaml;
+
+/// Ways to handle an attribute which is set by an `ADD[...]` and its `ID[...]`
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Conflict {
+    /// Keep the declared value and report the addition
+    Error,
+    /// Replace the declared value by the added one
+    Override,
+    /// Collect both values in a list
+    Append,
+}
+
+impl FromStr for Conflict {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Conflict, String> {
+        match s {
+            "error" => Ok(Conflict::Error),
+            "override" => Ok(Conflict::Override),
+            "append" => Ok(Conflict::Append),
+            _ => Err(format!("unknown conflict rule `{}`", s)),
+        }
+    }
+}
+
+pub struct MergeConfig {
+    /// Identifiers declaring a name, e.g. `ID`
+    pub ids: Vec<String>,
+    /// Identifiers adding attributes to a declared name, e.g. `ADD`
+    pub adds: Vec<String>,
+    pub conflict: Conflict,
+}
+
+impl Default for MergeConfig {
+    fn default() -> MergeConfig {
+        MergeConfig {
+            ids: vec!["ID".to_string()],
+            adds: vec!["ADD".to_string()],
+            conflict: Conflict::Error,
+        }
+    }
+}
+
+/// Turns `value` into a list, lists stay as they are
+fn into_list(value: Yaml) -> Vec<Yaml> {
+    match value {
+        Yaml::Array(values) => values,
+        value => vec![value],
+    }
+}
+
+/// Merges `key: value` of an addition into `attributes`, returns the kept value on an error
+pub(crate) fn merge_attribute(
+    attributes: &mut Yaml,
+    key: &Yaml,
+    value: &Yaml,
+    conflict: Conflict,
+) -> Option<Yaml> {
+    let hash = match attributes {
+        Yaml::Hash(hash) => hash,
+        _ => return None,
+    };
+    let declared = match hash.get_mut(key) {
+        Some(declared) => declared,
+        None => {
+            hash.insert(key.clone(), value.clone());
+            return None;
+        }
+    };
+    match conflict {
+        _ if declared == value => {}
+        Conflict::Error => return Some(declared.clone()),
+        Conflict::Override => *declared = value.clone(),
+        Conflict::Append => {
+            let mut values = into_list(declared.clone());
+            for value in into_list(value.clone()) {
+                if !values.contains(&value) {
+                    values.push(value);
+                }
+            }
+            *declared = Yaml::Array(values);
+        }
+    }
+    None
+}
+
+/// Merges the attributes of each `ADD[NAME, ...]` into the first `ID[NAME, ...]`
+///
+/// The additions are read in order of their position and removed from the results,
+/// additions which can not be merged stay and are reported.
+pub fn merge_additions(
+    results: Vec<Result>,
+    config: &MergeConfig,
+) -> (Vec<Result>, Vec<Diagnostic>) {
+    let is_one_of = |result: &Result, idents: &[String]| {
+        result.range != IdentRange::Tag && idents.contains(&result.ident)
+    };
+    // The attributes of the first declaration of each name
+    let mut declarations: HashMap<String, Yaml> = HashMap::new();
+    for result in results
+        .iter()
+        .filter(|result| is_one_of(result, &config.ids))
+    {
+        let (name, attributes) = parse_annotation(result);
+        declarations.entry(name).or_insert(attributes);
+    }
+
+    let mut additions: Vec<usize> = (0..results.len())
+        .filter(|index| is_one_of(&results[*index], &config.adds))
+        .collect();
+    additions.sort_by(|a, b| results[*a].position().cmp(&results[*b].position()));
+
+    let mut diagnostics = Vec::new();
+    let mut merged = vec![false; results.len()];
+    for index in additions {
+        let addition = &results[index];
+        let (name, added) = parse_annotation(addition);
+        let declared = declarations.contains_key(&name);
+        let declaration = declarations
+            .get_mut(&name)
+            .filter(|attributes| attributes.as_hash().is_some());
+        let (declaration, added) = match (declaration, added) {
+            (Some(declaration), Yaml::Hash(added)) => (declaration, added),
+            (declaration, added) => {
+                let ident = &addition.ident;
+                let (code, message) = if added.is_null() {
+                    (
+                        "unreadable-addition",
+                        format!("`{}` of `{}` can not be read as yaml", ident, name),
+                    )
+                } else if declaration.is_none() && declared {
+                    (
+                        "unreadable-declaration",
+                        format!(
+                            "`{}` extends `{}` whose declaration can not be read as yaml",
+                            ident, name
+                        ),
+                    )
+                } else {
+                    (
+                        "dangling-addition",
+                        format!("`{}` extends `{}` which is not declared", ident, name),
+                    )
+                };
+                diagnostics.push(
+                    Diagnostic::new(code, message, addition.line, addition.column)
+                        .with_path(addition.get_path()),
+                );
+                continue;
+            }
+        };
+        let ident = Yaml::String(addition.ident.clone());
+        for (key, value) in added.iter().filter(|(key, _)| **key != ident) {
+            if let Some(kept) = merge_attribute(declaration, key, value, config.conflict) {
+                diagnostics.push(
+                    Diagnostic::new(
+                        "add-conflict",
+                        format!(
+                            "`{}` sets `{}` of `{}` to {}, but it is declared as {}",
+                            addition.ident,
+                            to_flow(key),
+                            name,
+                            to_flow(value),
+                            to_flow(&kept)
+                        ),
+                        addition.line,
+                        addition.column,
+                    )
+                    .with_path(addition.get_path()),
+                );
+            }
+        }
+        merged[index] = true;
+    }
+
+    let mut output = Vec::new();
+    for (index, mut result) in results.iter().cloned().enumerate() {
+        if merged[index] {
+            continue;
+        }
+        if is_one_of(&result, &config.ids) {
+            // Only the first declaration gets the merged attributes, so each is written once
+            let (name, _) = parse_annotation(&result);
+            if let Some(attributes) = declarations.remove(&name) {
+                write_annotation(&mut result, &attributes);
+            }
+        }
+        output.push(result);
+    }
+    (output, diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::merge::{merge_additions, Conflict, MergeConfig};
+    use crate::test_support::{curt, prints, texts};
+
+    fn merge(test_data: &str, conflict: Conflict) -> (Vec<String>, Vec<String>) {
+        let config = MergeConfig {
+            conflict,
+            ..MergeConfig::default()
+        };
+        let (results, diagnostics) =
+            merge_additions(curt(&["ID", "ADD"], test_data, |_| {}), &config);
+        (texts(&results), prints(&diagnostics))
+    }
+
+    #[test]
+    fn test_merge_additions() {
+        let test_data = "ADD[REQ::A, status: done]\nID[REQ::A, tags: [a]]\nID[REQ::B]\nADD[REQ::A, tags: b, note: \"x, y\"]\nADD[REQ::C, x: 1]\nID[REQ::D, a: b: c] ADD[REQ::D, x: 1] ADD[REQ::A, y: z: 1]";
+        let (results, diagnostics) = merge(test_data, Conflict::Append);
+        assert_eq!(
+            results,
+            [
+                "{ID: REQ::A, tags: [a, b], status: done, note: \"x, y\"}",
+                "{ID: REQ::B}",
+                "{ADD: REQ::C, x: 1}",
+                "{ID: REQ::D, a: b: c}",
+                "{ADD: REQ::D, x: 1}",
+                "{ADD: REQ::A, y: z: 1}"
+            ]
+        );
+        assert_eq!(
+            diagnostics,
+            [
+                "<stdin>:5:1: dangling-addition: `ADD` extends `REQ::C` which is not declared",
+                "<stdin>:6:21: unreadable-declaration: `ADD` extends `REQ::D` whose declaration can not be read as yaml",
+                "<stdin>:6:39: unreadable-addition: `ADD` of `REQ::A` can not be read as yaml"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_conflicts() {
+        let test_data = "ID[REQ::A, status: open, tags: [a]]\nADD[REQ::A, status: done, tags: [a]]";
+        let (results, diagnostics) = merge(test_data, Conflict::Error);
+        assert_eq!(results, ["{ID: REQ::A, status: open, tags: [a]}"]);
+        assert_eq!(
+            diagnostics,
+            ["<stdin>:2:1: add-conflict: `ADD` sets `status` of `REQ::A` to done, but it is declared as open"]
+        );
+        let (results, diagnostics) = merge(test_data, Conflict::Override);
+        assert_eq!(results, ["{ID: REQ::A, status: done, tags: [a]}"]);
+        assert!(diagnostics.is_empty());
+        let (results, _) = merge(test_data, Conflict::Append);
+        assert_eq!(results, ["{ID: REQ::A, status: [open, done], tags: [a]}"]);
+    }
+}
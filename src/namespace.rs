@@ -0,0 +1,232 @@
+use crate::trace::{self, TraceConfig};
+use crate::{IdentRange, Payload, Result};
+
+/// Checks whether `c` may stand in front of a name within a payload
+fn is_separator(c: char) -> bool {
+    c.is_whitespace() || c == ',' || c == '[' || c == ':'
+}
+
+/// Checks whether `c` ends a name within a payload
+fn is_name_end(c: char) -> bool {
+    c.is_whitespace() || c == ',' || c == ']' || c == '}'
+}
+
+/// Prefixes each relative name like `::name` in `payload` with `base`
+///
+/// Only the name of the annotation and the values of the attributes in `links` are resolved,
+/// so `ID[::a, implements: ::b, caption: see ::c]` keeps `::c`.
+/// Quoted scalars like `"a ::b"` or `'::c'` are left as they are.
+fn resolve_payload(payload: &str, base: &str, links: &[String]) -> String {
+    let chars: Vec<char> = payload.chars().collect();
+    let mut resolved = String::new();
+    let mut previous = ' ';
+    let mut quote: Option<char> = None;
+    let mut escaped = false;
+    // Position within the top level entries like `::a` or `implements: [::b]`
+    let mut depth: usize = 0;
+    let mut entry = 0;
+    let mut entry_text = String::new();
+    let mut key: Option<String> = None;
+    for (i, c) in chars.iter().enumerate() {
+        match quote {
+            // `\"` escapes within double quotes and `''` within single quotes
+            Some(_) if escaped => escaped = false,
+            Some('"') if *c == '\\' => escaped = true,
+            Some('\'') if *c == '\'' && chars.get(i + 1) == Some(&'\'') => escaped = true,
+            Some(open) if *c == open => quote = None,
+            Some(_) => {}
+            None if (*c == '"' || *c == '\'') && (is_separator(previous) || i == 0) => {
+                quote = Some(*c)
+            }
+            None => {}
+        }
+        let resolves = match &key {
+            None => entry == 0,
+            Some(key) => links.iter().any(|link| link == key),
+        };
+        if resolves
+            && quote.is_none()
+            && *c == ':'
+            && is_separator(previous)
+            && chars.get(i + 1) == Some(&':')
+        {
+            // A single `:` in front marks a key, `::` the start of a relative name
+            let is_relative = previous != ':' || i < 2 || chars[i - 2] != ':';
+            let follows = chars.get(i + 2).copied().unwrap_or(' ');
+            if is_relative && !is_name_end(follows) {
+                resolved.push_str(base);
+            }
+        }
+        if quote.is_none() {
+            match c {
+                '[' | '{' => depth += 1,
+                ']' | '}' => depth = depth.saturating_sub(1),
+                ',' if depth == 0 => {
+                    entry += 1;
+                    entry_text.clear();
+                    key = None;
+                }
+                ':' if depth == 0
+                    && key.is_none()
+                    && previous != ':'
+                    && chars.get(i + 1).is_none_or(|next| next.is_whitespace()) =>
+                {
+                    key = Some(entry_text.trim().to_string());
+                }
+                _ => {}
+            }
+        }
+        if depth == 0 && *c != ',' {
+            entry_text.push(*c);
+        }
+        resolved.push(*c);
+        previous = *c;
+    }
+    resolved
+}
+
+/// Checks whether `name` declares a namespace, like `REQ::Extract_yaml` or `IMPL::extraction::`
+fn is_namespace(name: &str) -> bool {
+    !name.starts_with("::") && name.contains("::")
+}
+
+/// Resolves relative names like `ID[::name]` against the most recent namespace
+///
+/// Qualified IDs like `ID[REQ::Extract_yaml]` and namespaces like `ID[IMPL::extraction::]`
+/// become the base of all following relative names within the annotations, so
+/// `ID[::read_any_file]` is written as `ID[REQ::Extract_yaml::read_any_file]`.
+/// Only names of annotations and of the attributes in `config.links` are resolved, payloads
+/// of plain words are left as they are. Without any such declaration the results are left untouched.
+pub fn resolve_namespaces(results: &mut [Result], config: &TraceConfig) {
+    let is_id = |result: &Result| {
+        result.get_range() != IdentRange::Tag
+            && config.ids.iter().any(|id| id == result.get_ident())
+    };
+    let declares_namespace = results
+        .iter()
+        .any(|result| is_id(result) && is_namespace(&trace::parse_annotation(result).0));
+    if !declares_namespace {
+        return;
+    }
+    let mut order: Vec<usize> = (0..results.len())
+        .filter(|index| results[*index].get_range() != IdentRange::Tag)
+        .collect();
+    order.sort_by(|a, b| results[*a].position().cmp(&results[*b].position()));
+
+    let mut base: Option<String> = None;
+    let mut path: Option<String> = None;
+    for index in order {
+        let result = &mut results[index];
+        if result.path != path {
+            // Each file starts without a base
+            base = None;
+            path = result.path.clone();
+        }
+        if result.kind == Payload::Words {
+            continue;
+        }
+        let was_relative = trace::parse_annotation(result).0.starts_with("::");
+        if let Some(base) = &base {
+            let payload = resolve_payload(result.get_payload(), base, &config.links);
+            if payload != result.get_payload() {
+                result.set_payload(payload);
+            }
+        }
+        // Relative IDs only become the base if they declare a namespace like `ID[::sub::]`
+        let (name, _) = trace::parse_annotation(result);
+        if is_id(result) && is_namespace(&name) && (!was_relative || name.ends_with("::")) {
+            base = Some(name.trim_end_matches("::").to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::namespace::resolve_payload;
+    use crate::trace::TraceConfig;
+    use crate::{IdentRange, Indicators, Payload, YogurtYaml};
+
+    #[test]
+    fn test_resolve_payload() {
+        let links = TraceConfig::default().links;
+        assert_eq!(resolve_payload("::a, x: 1", "REQ", &links), "REQ::a, x: 1");
+        assert_eq!(
+            resolve_payload(
+                "IMPL::a, implements: [::b, REQ::c], tests: ::d",
+                "REQ",
+                &links
+            ),
+            "IMPL::a, implements: [REQ::b, REQ::c], tests: REQ::d"
+        );
+        assert_eq!(
+            resolve_payload("a, b: \"::\", c:: d", "REQ", &links),
+            "a, b: \"::\", c:: d"
+        );
+        assert_eq!(
+            resolve_payload(
+                "::a, tests: \"x \\\" ::y\", implements: 'it''s ::z', tests: don't ::w",
+                "REQ",
+                &links
+            ),
+            "REQ::a, tests: \"x \\\" ::y\", implements: 'it''s ::z', tests: don't REQ::w"
+        );
+        // Free text and other attributes keep their relative names
+        assert_eq!(
+            resolve_payload(
+                "::a, caption: see ::c, depends: [::d], implements: {x: ::e}",
+                "REQ",
+                &links
+            ),
+            "REQ::a, caption: see ::c, depends: [::d], implements: {x: REQ::e}"
+        );
+        assert_eq!(resolve_payload("x: ::a", "REQ", &links), "x: ::a");
+        assert_eq!(
+            resolve_payload("::a, depends: ::d", "REQ", &["depends".to_string()]),
+            "REQ::a, depends: REQ::d"
+        );
+    }
+
+    #[test]
+    fn test_resolve_namespaces() {
+        let test_data = "REF[::early]\nID[REQ::Extract_yaml]\nID[::read_any_file]\nID[::performance, required: optional]\nID[::performance::syntax_bracket]\nREF[::performance]\nID[IMPL::extraction::]\nID[::scan, implements: REQ::Extract_yaml::read_any_file]\nID[IMPL::other, implements: [::scan]]";
+        let indicator_lists = vec![Indicators::new(&["ID", "REF"], IdentRange::Brackets)];
+        let mut curt = YogurtYaml::new(&indicator_lists);
+        curt.curt_clear(&mut test_data.to_string());
+        curt.resolve_namespaces(&TraceConfig::default());
+        let result: Vec<&str> = curt
+            .get_results()
+            .iter()
+            .map(|result| result.get_text().as_str())
+            .collect();
+        assert_eq!(
+            result,
+            [
+                "{REF: ::early}",
+                "{ID: REQ::Extract_yaml}",
+                "{ID: REQ::Extract_yaml::read_any_file}",
+                "{ID: REQ::Extract_yaml::performance, required: optional}",
+                "{ID: REQ::Extract_yaml::performance::syntax_bracket}",
+                "{REF: REQ::Extract_yaml::performance}",
+                "{ID: IMPL::extraction::}",
+                "{ID: IMPL::extraction::scan, implements: REQ::Extract_yaml::read_any_file}",
+                "{ID: IMPL::other, implements: [IMPL::extraction::scan]}"
+            ]
+        );
+
+        // Without a namespace declaration relative names stay as they are
+        let mut curt = YogurtYaml::new(&indicator_lists);
+        curt.curt_clear(&mut "ID[A] ID[::b]".to_string());
+        curt.resolve_namespaces(&TraceConfig::default());
+        assert_eq!(curt.get_results()[1].get_text(), "{ID: ::b}");
+
+        // Payloads of plain words are no names
+        let indicator_lists = vec![
+            Indicators::new(&["ID"], IdentRange::Brackets),
+            Indicators::new(&["NOTE"], IdentRange::Rounds).with_payload(Payload::Words),
+        ];
+        let mut curt = YogurtYaml::new(&indicator_lists);
+        curt.curt_clear(&mut "ID[REQ::A] NOTE(::b is next)".to_string());
+        curt.resolve_namespaces(&TraceConfig::default());
+        assert_eq!(curt.get_results()[1].get_text(), "{NOTE: ::b is next}");
+    }
+}
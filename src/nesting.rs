@@ -0,0 +1,197 @@
+use crate::flow::flow_value;
+use crate::tags::{find_annotations, move_into_annotations};
+use crate::{IdentRange, Result};
+
+/// Finds the `close` char matching the `open` char at `chars[from]`
+fn find_closing(chars: &[char], from: usize, open: char, close: char) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in chars.iter().enumerate().skip(from) {
+        if *c == open {
+            depth += 1;
+        } else if *c == close {
+            depth -= 1;
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+    }
+    None
+}
+
+/// Finds the next annotation of `child` like `REF[...]` in `chars` from `cursor` on
+///
+/// The annotation is searched instead of taken from the offsets of `child`,
+/// as the payload may already have been changed, e.g. by aggregated tags.
+fn find_child(chars: &[char], cursor: usize, child: &Result) -> Option<(usize, usize)> {
+    let (open, close) = child.range.delimiters();
+    let mut pattern: Vec<char> = child.ident.chars().collect();
+    pattern.push(open);
+    let mut from = cursor;
+    while from + pattern.len() <= chars.len() {
+        let is_boundary = from == 0 || !chars[from - 1].is_alphanumeric();
+        if is_boundary && chars[from..].starts_with(&pattern) {
+            let to = find_closing(chars, from + pattern.len() - 1, open, close)?;
+            return Some((from, to));
+        }
+        from += 1;
+    }
+    None
+}
+
+/// Replaces each annotation of `children` within the payload of `result` by `replace`,
+/// which gets the child and its annotation as written
+///
+/// Returns which of the children were found.
+fn replace_children<F>(result: &mut Result, children: &[&Result], replace: F) -> Vec<bool>
+where
+    F: Fn(&Result, &[char]) -> Vec<char>,
+{
+    let mut chars: Vec<char> = result.payload.chars().collect();
+    let mut cursor = 0;
+    let mut found = Vec::new();
+    for child in children {
+        match find_child(&chars, cursor, child) {
+            Some((from, to)) => {
+                let text = replace(child, &chars[from..=to]);
+                cursor = from + text.len();
+                chars.splice(from..=to, text);
+                found.push(true);
+            }
+            None => found.push(false),
+        }
+    }
+    result.set_payload(chars.into_iter().collect());
+    found
+}
+
+/// Replaces each annotation of `children` within the payload of `result` by its yaml
+fn nest_children(result: &mut Result, children: &[&Result]) -> Vec<bool> {
+    replace_children(result, children, |child, _| child.text.chars().collect())
+}
+
+/// Writes each annotation within the payload of another one as quoted string into it
+///
+/// `ID[A, depends: [REF[B]]]` becomes `{ID: A, depends: ["REF[B]"]}` instead of
+/// `{REF: B}` and `{ID: A, depends: [REF[B]]}`, so the attributes can be read as yaml
+/// the way they are written. Tags are left as they are.
+pub fn quote_annotations(results: Vec<Result>) -> Vec<Result> {
+    let mut children: Vec<Vec<usize>> = vec![Vec::new(); results.len()];
+    for (index, parent) in find_annotations(&results).into_iter().enumerate() {
+        if let (Some(parent), false) = (parent, results[index].range == IdentRange::Tag) {
+            children[parent].push(index);
+        }
+    }
+    let mut quoted = results.clone();
+    let mut removed = vec![false; results.len()];
+    for (parent, children) in children.iter_mut().enumerate() {
+        if children.is_empty() {
+            continue;
+        }
+        children.sort_by_key(|index| results[*index].start);
+        let texts: Vec<&Result> = children.iter().map(|index| &results[*index]).collect();
+        let found = replace_children(&mut quoted[parent], &texts, |_, source| {
+            let source: String = source.iter().collect();
+            flow_value(&source).chars().collect()
+        });
+        for (index, found) in children.iter().zip(found) {
+            removed[*index] = found;
+        }
+    }
+
+    quoted
+        .into_iter()
+        .zip(removed)
+        .filter(|(_, removed)| !removed)
+        .map(|(result, _)| result)
+        .collect()
+}
+
+/// Moves each annotation within the payload of another one into it
+///
+/// `ID[A, depends: REF[B]]` becomes `{ID: A, depends: {REF: B}}` instead of
+/// `{REF: B}` and `{ID: A, depends: REF[B]}`. Tags within an annotation are moved into its
+/// `tags: [...]` list. An identifier within an annotation of the same identifier is not
+/// scanned on its own, so it stays part of the payload.
+pub fn nest_annotations(results: Vec<Result>) -> Vec<Result> {
+    let results = move_into_annotations(results);
+    let mut children: Vec<Vec<usize>> = vec![Vec::new(); results.len()];
+    for (index, parent) in find_annotations(&results).into_iter().enumerate() {
+        if let Some(parent) = parent {
+            children[parent].push(index);
+        }
+    }
+
+    let mut nested = results.clone();
+    let mut removed = vec![false; results.len()];
+    let mut order: Vec<usize> = (0..results.len()).collect();
+    order.sort_by_key(|index| results[*index].end - results[*index].start);
+    for parent in order {
+        let children = &mut children[parent];
+        if children.is_empty() {
+            continue;
+        }
+        children.sort_by_key(|index| results[*index].start);
+        let texts: Vec<&Result> = children.iter().map(|index| &nested[*index]).collect();
+        let mut result = nested[parent].clone();
+        let found = nest_children(&mut result, &texts);
+        nested[parent] = result;
+        for (index, found) in children.iter().zip(found) {
+            removed[*index] = found;
+        }
+    }
+
+    nested
+        .into_iter()
+        .zip(removed)
+        .filter(|(_, removed)| !removed)
+        .map(|(result, _)| result)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{curt, texts};
+    use crate::TagScope;
+
+    #[test]
+    fn test_nest_annotations() {
+        let test_data =
+            "REF[X] ID[A, depends: REF[B], other: [REF[C], REF[D, note: ADD[E]]]]\nID[F]";
+        let result = curt(&["ID", "REF", "ADD"], test_data, |curt| {
+            curt.nest_annotations()
+        });
+        assert_eq!(
+            texts(&result),
+            [
+                "{REF: X}",
+                "{ID: A, depends: {REF: B}, other: [{REF: C}, {REF: D, note: {ADD: E}}]}",
+                "{ID: F}"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nest_annotations_with_tags() {
+        let test_data = "ID[A, #a, depends: REF[B, #b]] #c";
+        let result = curt(&["ID", "REF"], test_data, |curt| {
+            curt.aggregate_tags(TagScope::Annotation);
+            curt.nest_annotations();
+        });
+        assert_eq!(
+            texts(&result),
+            [
+                "{ID: A, depends: {REF: B, tags: [b]}, tags: [a]}",
+                "{tags: [c]}"
+            ]
+        );
+        // Without a tag scope only the tags within annotations are moved
+        let result = curt(&["ID", "REF"], test_data, |curt| curt.nest_annotations());
+        assert_eq!(
+            texts(&result),
+            [
+                "{ID: A, depends: {REF: B, tags: [b]}, tags: [a]}",
+                "{tag: c}"
+            ]
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use crate::tags::find_annotations;
+use crate::trace::{parse_annotation, write_annotation};
+use crate::{IdentRange, Indicators, Payload, Result, YogurtYaml};
+
+/// Writes `result` as annotation in normalized flow yaml, `None` if it can not be normalized
+fn normalize(result: &Result) -> Option<String> {
+    let (_, attributes) = parse_annotation(result);
+    attributes.as_hash()?;
+    let mut normalized = result.clone();
+    write_annotation(&mut normalized, &attributes);
+    let (open, close) = result.range.delimiters();
+    let annotation = format!("{}{}{}{}", result.ident, open, normalized.payload, close);
+
+    // Read back the same way, e.g. `X<a: "b>c">` has to keep its quotes
+    let idents = [result.ident.as_str()];
+    let indicators = [Indicators::new(&idents, result.range)];
+    let mut curt = YogurtYaml::new(&indicators);
+    curt.curt_clear(&mut annotation.clone());
+    match curt.get_results().as_slice() {
+        [found]
+            if found.start == 0
+                && found.end + 1 == annotation.chars().count()
+                && parse_annotation(found).1 == attributes =>
+        {
+            Some(annotation)
+        }
+        _ => None,
+    }
+}
+
+/// Rewrites each annotation of `results` within `source` in normalized flow yaml
+///
+/// `ID[A,caption:   'x y']` becomes `ID[A, caption: x y]`. Annotations spanning several lines,
+/// containing other results, of plain words or without valid yaml are left as they are,
+/// so comment markers and nested annotations stay untouched.
+pub fn normalize_annotations(source: &str, results: &[Result]) -> String {
+    let mut contains_other = vec![false; results.len()];
+    for annotation in find_annotations(results).into_iter().flatten() {
+        contains_other[annotation] = true;
+    }
+    let mut replacements: Vec<(usize, usize, String)> = Vec::new();
+    for (result, contains_other) in results.iter().zip(contains_other) {
+        if result.range == IdentRange::Tag
+            || result.kind != Payload::Yaml
+            || result.payload.contains('\n')
+            || contains_other
+        {
+            continue;
+        }
+        if let Some(annotation) = normalize(result) {
+            replacements.push((result.start, result.end, annotation));
+        }
+    }
+    replacements.sort_by_key(|(start, _, _)| *start);
+
+    let mut chars: Vec<char> = source.chars().collect();
+    for (start, end, annotation) in replacements.into_iter().rev() {
+        chars.splice(start..=end, annotation.chars());
+    }
+    chars.into_iter().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::normalize::normalize_annotations;
+    use crate::{IdentRange, Indicators, YogurtYaml};
+
+    #[test]
+    fn test_normalize_annotations() {
+        let test_data = "// ID[A,caption:   'x y', tags: [ a,b ]]\nä X<k: \"b>c\"> ID[B,\n x: 1] REF[C, d: REF[D]] ID[E, a: b: c]\n";
+        let indicator_lists = vec![
+            Indicators::new(&["ID", "REF"], IdentRange::Brackets),
+            Indicators::new(&["X"], IdentRange::Crickets),
+        ];
+        let mut curt = YogurtYaml::new(&indicator_lists);
+        curt.curt_clear(&mut test_data.to_string());
+        assert_eq!(
+            normalize_annotations(test_data, curt.get_results()),
+            "// ID[A, caption: x y, tags: [a, b]]\nä X<k: \"b>c\"> ID[B,\n x: 1] REF[C, d: REF[D]] ID[E, a: b: c]\n"
+        );
+    }
+}
@@ -0,0 +1,530 @@
+use crate::flow::{flow_entry, to_flow};
+use crate::merge::{merge_attribute, Conflict};
+use crate::query::select;
+use crate::trace::{parse_annotation, TraceConfig};
+use crate::{IdentRange, Result};
+use std::io::{self, Write};
+use std::str::FromStr;
+use yaml_rust::yaml::Hash;
+use yaml_rust::{Yaml, YamlEmitter, YamlLoader};
+
+/// Ways to write extracted results
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Format {
+    /// A yaml list with one flow mapping per line, like `- {ID: A}`
+    Yaml,
+    /// A yaml list in block style
+    YamlBlock,
+    /// A json array
+    Json,
+    /// One json object per line
+    Jsonl,
+    /// One row per result and one column per attribute
+    Csv,
+    /// One line per result in the form of compilers and grep, like `path:line:column: ID: A`
+    Grep,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Format, String> {
+        match s {
+            "yaml" => Ok(Format::Yaml),
+            "yaml-block" => Ok(Format::YamlBlock),
+            "json" => Ok(Format::Json),
+            "jsonl" => Ok(Format::Jsonl),
+            "csv" => Ok(Format::Csv),
+            "grep" => Ok(Format::Grep),
+            _ => Err(format!("unknown format `{}`", s)),
+        }
+    }
+}
+
+/// Attributes of `result`, `{IDENT: payload}` if its payload is no valid yaml
+pub(crate) fn attributes(result: &Result) -> Yaml {
+    match YamlLoader::load_from_str(result.get_text()).as_deref() {
+        Ok([attributes @ Yaml::Hash(_)]) => attributes.clone(),
+        _ => {
+            let mut hash = Hash::new();
+            hash.insert(
+                Yaml::String(result.ident.clone()),
+                Yaml::String(result.payload.clone()),
+            );
+            Yaml::Hash(hash)
+        }
+    }
+}
+
+/// Keys of the metadata of each result, in the order they are written
+pub const METADATA_KEYS: [&str; 5] = ["path", "line", "column", "ident", "range"];
+
+/// Where `result` was found, the keys come first in each format
+fn metadata(result: &Result) -> Vec<(&'static str, Yaml)> {
+    let values = [
+        result
+            .get_path()
+            .map_or(Yaml::Null, |path| Yaml::String(path.to_string())),
+        Yaml::Integer(result.line as i64),
+        Yaml::Integer(result.column as i64),
+        Yaml::String(result.ident.clone()),
+        Yaml::String(result.range.get_name().to_string()),
+    ];
+    METADATA_KEYS.iter().copied().zip(values).collect()
+}
+
+/// `result` as yaml, with its metadata the attributes are nested as `annotation`
+fn entry(result: &Result, with_metadata: bool) -> Yaml {
+    if !with_metadata {
+        return attributes(result);
+    }
+    let mut hash = Hash::new();
+    for (key, value) in metadata(result) {
+        hash.insert(Yaml::String(key.to_string()), value);
+    }
+    hash.insert(Yaml::String("annotation".to_string()), attributes(result));
+    Yaml::Hash(hash)
+}
+
+fn json_string(value: &str) -> String {
+    let mut json = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Writes `yaml` as json in a single line, keys which are no strings are written as flow yaml
+pub fn to_json(yaml: &Yaml) -> String {
+    match yaml {
+        Yaml::String(value) => json_string(value),
+        Yaml::Integer(value) => value.to_string(),
+        Yaml::Real(_) => match yaml.as_f64() {
+            Some(value) if value.is_finite() => value.to_string(),
+            _ => "null".to_string(),
+        },
+        Yaml::Boolean(value) => value.to_string(),
+        Yaml::Array(values) => {
+            let values: Vec<String> = values.iter().map(to_json).collect();
+            format!("[{}]", values.join(","))
+        }
+        Yaml::Hash(hash) => {
+            let entries: Vec<String> = hash
+                .iter()
+                .map(|(key, value)| {
+                    let key = match key {
+                        Yaml::String(key) => key.clone(),
+                        key => to_flow(key),
+                    };
+                    format!("{}:{}", json_string(&key), to_json(value))
+                })
+                .collect();
+            format!("{{{}}}", entries.join(","))
+        }
+        _ => "null".to_string(),
+    }
+}
+
+/// A single csv field, quoted if needed
+fn csv_field(value: &Yaml) -> String {
+    let field = match value {
+        Yaml::String(value) => value.clone(),
+        Yaml::Null | Yaml::BadValue => String::new(),
+        value => to_flow(value),
+    };
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field
+    }
+}
+
+/// `result` in a single line after its location, like `path:3:1: ID: A, caption: a`
+fn grep_line(result: &Result) -> String {
+    let text = result.get_text().trim();
+    let text = text
+        .strip_prefix('{')
+        .and_then(|text| text.strip_suffix('}'))
+        .unwrap_or(text);
+    let text: Vec<&str> = text.lines().map(str::trim).collect();
+    format!("{}: {}", result.get_location(), text.join(" "))
+}
+
+/// Writes one row per result, the columns are all attribute keys in order of appearance
+fn write_csv<W: Write>(out: &mut W, results: &[Result], with_metadata: bool) -> io::Result<()> {
+    let rows: Vec<Yaml> = results.iter().map(attributes).collect();
+    let mut columns: Vec<Yaml> = Vec::new();
+    if with_metadata {
+        columns.extend(METADATA_KEYS.map(|key| Yaml::String(key.to_string())));
+    }
+    let attribute_columns = columns.len();
+    for row in &rows {
+        if let Yaml::Hash(hash) = row {
+            for key in hash.keys() {
+                if !columns[attribute_columns..].contains(key) {
+                    columns.push(key.clone());
+                }
+            }
+        }
+    }
+    let header: Vec<String> = columns.iter().map(csv_field).collect();
+    writeln!(out, "{}", header.join(","))?;
+    for (result, row) in results.iter().zip(&rows) {
+        let mut fields: Vec<String> = Vec::new();
+        if with_metadata {
+            fields.extend(metadata(result).iter().map(|(_, value)| csv_field(value)));
+        }
+        for key in &columns[attribute_columns..] {
+            let value = row.as_hash().and_then(|hash| hash.get(key));
+            fields.push(value.map_or(String::new(), csv_field));
+        }
+        writeln!(out, "{}", fields.join(","))?;
+    }
+    Ok(())
+}
+
+/// Writes `yaml` in block style, without the document start like `---\n- ID: A` or `--- []`
+fn write_block<W: Write>(out: &mut W, yaml: &Yaml) -> io::Result<()> {
+    let mut text = String::new();
+    YamlEmitter::new(&mut text)
+        .dump(yaml)
+        .map_err(|error| io::Error::other(format!("{:?}", error)))?;
+    let text = text.strip_prefix("---").unwrap_or(&text);
+    writeln!(out, "{}", text.trim_start())
+}
+
+/// Writes `entries` as json array with one entry per line
+fn write_json_array<W: Write>(out: &mut W, entries: &[Yaml]) -> io::Result<()> {
+    writeln!(out, "[")?;
+    for (index, entry) in entries.iter().enumerate() {
+        let separator = if index + 1 < entries.len() { "," } else { "" };
+        writeln!(out, "  {}{}", to_json(entry), separator)?;
+    }
+    writeln!(out, "]")
+}
+
+/// Appends `items` to the list `key` of `group`, a single value becomes a list
+fn push_items(group: &mut Yaml, key: &str, items: Vec<Yaml>) {
+    let key = Yaml::String(key.to_string());
+    merge_attribute(group, &key, &Yaml::Array(items), Conflict::Append);
+}
+
+/// Items of the `tags` list for a tag result like `{tag: a}` or `{tags: [a, b]}`
+fn tag_items(result: &Result) -> Vec<Yaml> {
+    match attributes(result) {
+        Yaml::Hash(hash) if hash.len() == 1 => match hash.front() {
+            Some((Yaml::String(key), Yaml::Array(items))) if key == "tags" => items.clone(),
+            Some((Yaml::String(key), name)) if key == "tag" => vec![name.clone()],
+            _ => vec![Yaml::Hash(hash)],
+        },
+        other => vec![other],
+    }
+}
+
+/// Groups the results by the names of the IDs of `config`, e.g. `{REQ::A: {caption: a}}`
+///
+/// Repeated declarations of a name are merged, values set differently are collected in a list.
+/// References and tags are gathered under the last ID before them within the same file,
+/// as lists named by their identifier and `tags`. Namespaces are left out, as is anything
+/// before the first ID or between a namespace and the next ID.
+pub fn group_by_name(results: &[Result], config: &TraceConfig) -> Yaml {
+    let mut ordered: Vec<&Result> = results.iter().collect();
+    ordered.sort_by(|a, b| a.position().cmp(&b.position()));
+
+    let mut groups = Hash::new();
+    let mut current: Option<Yaml> = None;
+    for (index, result) in ordered.iter().enumerate() {
+        if index > 0 && ordered[index - 1].path != result.path {
+            current = None;
+        }
+        if config.ids.contains(&result.ident) && result.range != IdentRange::Tag {
+            let (name, attributes) = parse_annotation(result);
+            if name.ends_with("::") {
+                current = None;
+                continue;
+            }
+            let key = Yaml::String(name);
+            let group = groups
+                .entry(key.clone())
+                .or_insert_with(|| Yaml::Hash(Hash::new()));
+            if let Yaml::Hash(attributes) = attributes {
+                let ident = Yaml::String(result.ident.clone());
+                for (key, value) in attributes.iter().filter(|(key, _)| **key != ident) {
+                    merge_attribute(group, key, value, Conflict::Append);
+                }
+            }
+            current = Some(key);
+            continue;
+        }
+        let group = match current.as_ref().and_then(|name| groups.get_mut(name)) {
+            Some(group) => group,
+            None => continue,
+        };
+        if result.range == IdentRange::Tag {
+            push_items(group, "tags", tag_items(result));
+        } else if config.refs.contains(&result.ident) {
+            let (to, _) = parse_annotation(result);
+            push_items(group, &result.ident, vec![Yaml::String(to)]);
+        }
+    }
+    Yaml::Hash(groups)
+}
+
+/// Writes `results` grouped by name as a single document, see `group_by_name`
+///
+/// `Format::Yaml` writes a line per name like `REQ::A: {caption: a}`, `Format::Json`
+/// an object with a line per name. Csv and grep have no way to write a single document.
+pub fn write_grouped<W: Write>(
+    out: &mut W,
+    results: &[Result],
+    config: &TraceConfig,
+    format: Format,
+) -> io::Result<()> {
+    let groups = group_by_name(results, config);
+    let entries = groups.as_hash().into_iter().flatten();
+    match format {
+        Format::Yaml => {
+            for (name, attributes) in entries {
+                writeln!(out, "{}", flow_entry(name, attributes))?;
+            }
+        }
+        Format::YamlBlock => write_block(out, &groups)?,
+        Format::Json => {
+            let entries: Vec<String> = entries
+                .map(|(name, attributes)| {
+                    let entry = to_json(&Yaml::Hash(
+                        std::iter::once((name.clone(), attributes.clone())).collect(),
+                    ));
+                    format!("  {}", &entry[1..entry.len() - 1])
+                })
+                .collect();
+            writeln!(out, "{{\n{}\n}}", entries.join(",\n"))?;
+        }
+        Format::Jsonl => writeln!(out, "{}", to_json(&groups))?,
+        Format::Csv | Format::Grep => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "results grouped by name can only be written as yaml, yaml-block, json or jsonl",
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// Writes only the `fields` of each result into `out` as `format`, see `query::select`
+///
+/// Csv has one column per field, grep writes the fields after the location of each result.
+pub fn write_selected<W: Write>(
+    out: &mut W,
+    results: &[Result],
+    fields: &[String],
+    format: Format,
+) -> io::Result<()> {
+    let entries: Vec<Yaml> = results
+        .iter()
+        .map(|result| select(result, fields))
+        .collect();
+    match format {
+        Format::Yaml => {
+            for entry in &entries {
+                writeln!(out, "- {}", to_flow(entry))?;
+            }
+        }
+        Format::YamlBlock => write_block(out, &Yaml::Array(entries))?,
+        Format::Json => write_json_array(out, &entries)?,
+        Format::Jsonl => {
+            for entry in &entries {
+                writeln!(out, "{}", to_json(entry))?;
+            }
+        }
+        Format::Csv => {
+            let header: Vec<String> = fields
+                .iter()
+                .map(|field| csv_field(&Yaml::String(field.clone())))
+                .collect();
+            writeln!(out, "{}", header.join(","))?;
+            for entry in &entries {
+                // By field, as a field given twice is a single key of the entry
+                let row: Vec<String> = fields
+                    .iter()
+                    .map(|field| csv_field(&entry[field.as_str()]))
+                    .collect();
+                writeln!(out, "{}", row.join(","))?;
+            }
+        }
+        Format::Grep => {
+            for (result, entry) in results.iter().zip(&entries) {
+                let text = to_flow(entry);
+                writeln!(
+                    out,
+                    "{}: {}",
+                    result.get_location(),
+                    &text[1..text.len() - 1]
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Writes `results` into `out` as `format`, with their path, line, column, identifier and range
+/// if `with_metadata` is set
+///
+/// The default `Format::Yaml` and `Format::Grep` write the text of each result as is,
+/// the other formats read it as yaml first.
+pub fn write_results<W: Write>(
+    out: &mut W,
+    results: &[Result],
+    format: Format,
+    with_metadata: bool,
+) -> io::Result<()> {
+    match format {
+        Format::Yaml => {
+            for result in results {
+                if with_metadata {
+                    let fields: Vec<String> = metadata(result)
+                        .iter()
+                        .map(|(key, value)| format!("{}: {}", key, to_flow(value)))
+                        .collect();
+                    writeln!(
+                        out,
+                        "- {{{}, annotation: {}}}",
+                        fields.join(", "),
+                        result.get_text()
+                    )?;
+                } else {
+                    writeln!(out, "- {}", result.get_text())?;
+                }
+            }
+        }
+        Format::YamlBlock => {
+            let entries = results
+                .iter()
+                .map(|result| entry(result, with_metadata))
+                .collect();
+            write_block(out, &Yaml::Array(entries))?;
+        }
+        Format::Json => {
+            let entries: Vec<Yaml> = results
+                .iter()
+                .map(|result| entry(result, with_metadata))
+                .collect();
+            write_json_array(out, &entries)?;
+        }
+        Format::Jsonl => {
+            for result in results {
+                writeln!(out, "{}", to_json(&entry(result, with_metadata)))?;
+            }
+        }
+        Format::Csv => write_csv(out, results, with_metadata)?,
+        Format::Grep => {
+            // The location already is the metadata
+            for result in results {
+                writeln!(out, "{}", grep_line(result))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::output::{write_grouped, write_results, write_selected, Format};
+    use crate::test_support::curt;
+    use crate::trace::TraceConfig;
+
+    fn write(format: Format, with_metadata: bool) -> String {
+        let test_data =
+            "ID[A, caption: \"a, b\", tags: [x]]\nID[B, count: 2, ratio: 0.5]\nID[C, a: b: c] #t";
+        let results = curt(&["ID"], test_data, |_| {});
+        let mut out = Vec::new();
+        write_results(&mut out, &results, format, with_metadata).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_write_formats() {
+        assert_eq!(
+            write(Format::Yaml, false),
+            "- {ID: A, caption: \"a, b\", tags: [x]}\n- {ID: B, count: 2, ratio: 0.5}\n- {ID: C, a: b: c}\n- {tag: t}\n"
+        );
+        assert_eq!(
+            write(Format::Jsonl, false),
+            "{\"ID\":\"A\",\"caption\":\"a, b\",\"tags\":[\"x\"]}\n{\"ID\":\"B\",\"count\":2,\"ratio\":0.5}\n{\"ID\":\"C, a: b: c\"}\n{\"tag\":\"t\"}\n"
+        );
+        assert_eq!(
+            write(Format::Csv, false),
+            "ID,caption,tags,count,ratio,tag\nA,\"a, b\",[x],,,\nB,,,2,0.5,\n\"C, a: b: c\",,,,,\n,,,,,t\n"
+        );
+        assert_eq!(
+            write(Format::YamlBlock, false),
+            "- ID: A\n  caption: \"a, b\"\n  tags:\n    - x\n- ID: B\n  count: 2\n  ratio: 0.5\n- ID: \"C, a: b: c\"\n- tag: t\n"
+        );
+    }
+
+    #[test]
+    fn test_write_grep() {
+        let test_data = "ID[A,\n  caption: a]\n  #t";
+        let mut results = curt(&["ID"], test_data, |_| {});
+        results[1].path = Some("docs/a.adoc".to_string());
+        let mut out = Vec::new();
+        write_results(&mut out, &results, Format::Grep, false).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "<stdin>:1:1: ID: A, caption: a\ndocs/a.adoc:3:3: tag: t\n"
+        );
+    }
+
+    #[test]
+    fn test_write_metadata() {
+        assert_eq!(
+            write(Format::Yaml, true).lines().nth(1),
+            Some("- {path: ~, line: 2, column: 1, ident: ID, range: brackets, annotation: {ID: B, count: 2, ratio: 0.5}}")
+        );
+        assert_eq!(
+            write(Format::Json, true).lines().nth(4),
+            Some("  {\"path\":null,\"line\":3,\"column\":16,\"ident\":\"#\",\"range\":\"tag\",\"annotation\":{\"tag\":\"t\"}}")
+        );
+        assert_eq!(
+            write(Format::Csv, true).lines().next(),
+            Some("path,line,column,ident,range,ID,caption,tags,count,ratio,tag")
+        );
+    }
+
+    #[test]
+    fn test_write_grouped() {
+        let test_data = "ID[NS::] ID[NS::A, caption: a] #x\nREF[NS::B] ID[NS::B, tags: [y]] #z\nID[NS::A, caption: c] REF[NS::B]\nID[OTHER::] REF[NS::C] #w";
+        let results = curt(&["ID", "REF"], test_data, |_| {});
+        let config = TraceConfig::default();
+        let mut out = Vec::new();
+        write_grouped(&mut out, &results, &config, Format::Yaml).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "NS::A: {caption: [a, c], tags: [x], REF: [NS::B]}\nNS::B: {tags: [y, z]}\n"
+        );
+        let mut out = Vec::new();
+        assert!(write_grouped(&mut out, &results, &config, Format::Csv).is_err());
+    }
+
+    #[test]
+    fn test_write_selected() {
+        let test_data = "ID[A, caption: \"a, b\"]\nID[B, status: open]";
+        let results = curt(&["ID"], test_data, |_| {});
+        let fields = ["name", "caption", "name", "line"].map(String::from);
+        let mut out = Vec::new();
+        write_selected(&mut out, &results, &fields, Format::Csv).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "name,caption,name,line\nA,\"a, b\",A,1\nB,,B,2\n"
+        );
+    }
+}
@@ -0,0 +1,437 @@
+use crate::output::attributes;
+use crate::Result;
+use regex::Regex;
+use std::cmp::Ordering;
+use std::str::FromStr;
+use yaml_rust::yaml::Hash;
+use yaml_rust::Yaml;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Text(String),
+    Number(Yaml),
+    Operator(&'static str),
+}
+
+/// Operators by length, so `<=` is found before `<`
+const OPERATORS: [&str; 14] = [
+    "==", "!=", "!~", "<=", ">=", "&&", "||", "~", "<", ">", "!", "(", ")", "=",
+];
+
+fn tokenize(s: &str) -> std::result::Result<Vec<(usize, Token)>, String> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    while index < chars.len() {
+        let start = index;
+        let c = chars[index];
+        if c.is_whitespace() {
+            index += 1;
+        } else if c == '"' {
+            let mut text = String::new();
+            index += 1;
+            loop {
+                match chars.get(index) {
+                    None => return Err(format!("unterminated string at {}", start + 1)),
+                    Some('"') => break,
+                    Some('\\') => {
+                        match chars.get(index + 1) {
+                            Some('n') => text.push('\n'),
+                            Some('t') => text.push('\t'),
+                            Some(c @ ('"' | '\\')) => text.push(*c),
+                            // Kept as is, so regular expressions like `"\d"` read naturally
+                            Some(c) => {
+                                text.push('\\');
+                                text.push(*c);
+                            }
+                            None => return Err(format!("unterminated string at {}", start + 1)),
+                        }
+                        index += 1;
+                    }
+                    Some(c) => text.push(*c),
+                }
+                index += 1;
+            }
+            index += 1;
+            tokens.push((start, Token::Text(text)));
+        } else if c.is_ascii_digit()
+            || (c == '-' && chars.get(index + 1).is_some_and(char::is_ascii_digit))
+        {
+            index += 1;
+            while chars
+                .get(index)
+                .is_some_and(|c| c.is_ascii_digit() || *c == '.')
+            {
+                index += 1;
+            }
+            let number: String = chars[start..index].iter().collect();
+            let value = match number.parse::<i64>() {
+                Ok(value) => Yaml::Integer(value),
+                Err(_) if number.parse::<f64>().is_ok() => Yaml::Real(number),
+                Err(_) => return Err(format!("invalid number `{}` at {}", number, start + 1)),
+            };
+            tokens.push((start, Token::Number(value)));
+        } else if c.is_alphanumeric() || c == '_' {
+            while chars
+                .get(index)
+                .is_some_and(|c| c.is_alphanumeric() || ['_', '-', '.'].contains(c))
+            {
+                index += 1;
+            }
+            tokens.push((start, Token::Word(chars[start..index].iter().collect())));
+        } else {
+            let rest: String = chars[index..chars.len().min(index + 2)].iter().collect();
+            match OPERATORS
+                .iter()
+                .find(|operator| rest.starts_with(*operator))
+            {
+                Some(&"=") => {
+                    return Err(format!("unknown operator `=` at {}, use `==`", start + 1))
+                }
+                Some(operator) => {
+                    index += operator.len();
+                    tokens.push((start, Token::Operator(operator)));
+                }
+                None => return Err(format!("unexpected `{}` at {}", c, start + 1)),
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+#[derive(Clone, Debug)]
+enum Expression {
+    Has(String),
+    Compare(String, &'static str, Yaml),
+    Matches(String, Regex),
+    Not(Box<Expression>),
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    index: usize,
+    length: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index).map(|(_, token)| token)
+    }
+
+    /// Position of the next token for error messages, counted from 1
+    fn position(&self) -> usize {
+        self.tokens
+            .get(self.index)
+            .map_or(self.length, |(start, _)| *start)
+            + 1
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.peek().cloned();
+        self.index += 1;
+        token
+    }
+
+    fn expect(&mut self, operator: &str) -> std::result::Result<(), String> {
+        let position = self.position();
+        match self.next() {
+            Some(Token::Operator(found)) if found == operator => Ok(()),
+            _ => Err(format!("expected `{}` at {}", operator, position)),
+        }
+    }
+
+    fn field(&mut self) -> std::result::Result<String, String> {
+        let position = self.position();
+        match self.next() {
+            Some(Token::Word(field)) => Ok(field),
+            _ => Err(format!("expected a field at {}", position)),
+        }
+    }
+
+    fn or(&mut self) -> std::result::Result<Expression, String> {
+        let mut expression = self.and()?;
+        while self.peek() == Some(&Token::Operator("||")) {
+            self.index += 1;
+            expression = Expression::Or(Box::new(expression), Box::new(self.and()?));
+        }
+        Ok(expression)
+    }
+
+    fn and(&mut self) -> std::result::Result<Expression, String> {
+        let mut expression = self.unary()?;
+        while self.peek() == Some(&Token::Operator("&&")) {
+            self.index += 1;
+            expression = Expression::And(Box::new(expression), Box::new(self.unary()?));
+        }
+        Ok(expression)
+    }
+
+    fn unary(&mut self) -> std::result::Result<Expression, String> {
+        match self.peek() {
+            Some(Token::Operator("!")) => {
+                self.index += 1;
+                Ok(Expression::Not(Box::new(self.unary()?)))
+            }
+            Some(Token::Operator("(")) => {
+                self.index += 1;
+                let expression = self.or()?;
+                self.expect(")")?;
+                Ok(expression)
+            }
+            Some(Token::Word(word))
+                if word == "has"
+                    && self.tokens.get(self.index + 1).map(|(_, token)| token)
+                        == Some(&Token::Operator("(")) =>
+            {
+                self.index += 2;
+                let field = self.field()?;
+                self.expect(")")?;
+                Ok(Expression::Has(field))
+            }
+            _ => self.comparison(),
+        }
+    }
+
+    fn comparison(&mut self) -> std::result::Result<Expression, String> {
+        let field = self.field()?;
+        let position = self.position();
+        let operator = match self.next() {
+            Some(Token::Operator(operator))
+                if ["==", "!=", "~", "!~", "<", "<=", ">", ">="].contains(&operator) =>
+            {
+                operator
+            }
+            _ => {
+                return Err(format!(
+                    "expected an operator after `{}` at {}",
+                    field, position
+                ))
+            }
+        };
+        let position = self.position();
+        let value = match self.next() {
+            Some(Token::Text(text)) => Yaml::String(text),
+            Some(Token::Number(number)) => number,
+            Some(Token::Word(word)) if word == "true" || word == "false" => {
+                Yaml::Boolean(word == "true")
+            }
+            Some(Token::Word(word)) if word == "null" => Yaml::Null,
+            Some(Token::Word(word)) => {
+                return Err(format!(
+                    "expected a value at {}, quote strings like `\"{}\"`",
+                    position, word
+                ))
+            }
+            _ => return Err(format!("expected a value at {}", position)),
+        };
+        let expression = match operator {
+            "~" | "!~" => {
+                let pattern = match &value {
+                    Yaml::String(pattern) => pattern,
+                    _ => return Err(format!("expected a quoted pattern at {}", position)),
+                };
+                let regex = Regex::new(pattern).map_err(|error| error.to_string())?;
+                Expression::Matches(field, regex)
+            }
+            "!=" => Expression::Compare(field, "==", value),
+            operator => Expression::Compare(field, operator, value),
+        };
+        // `!=` and `!~` are the negation, so missing fields and lists without the value hold them
+        Ok(if operator.starts_with('!') {
+            Expression::Not(Box::new(expression))
+        } else {
+            expression
+        })
+    }
+}
+
+/// The value of `field` for `result`
+///
+/// `ident`, `name`, `path`, `line`, `column` and `range` are those of the result, any other
+/// field is looked up in the attributes of the annotation. Attributes of nested mappings are reached like `verified.by`.
+pub fn field(result: &Result, field: &str) -> Option<Yaml> {
+    match field {
+        "ident" => Some(Yaml::String(result.ident.clone())),
+        "name" => Some(Yaml::String(result.get_name())),
+        "path" => result.get_path().map(|path| Yaml::String(path.to_string())),
+        "line" => Some(Yaml::Integer(result.line as i64)),
+        "column" => Some(Yaml::Integer(result.column as i64)),
+        "range" => Some(Yaml::String(result.range.get_name().to_string())),
+        _ => {
+            let mut value = attributes(result);
+            for key in field.split('.') {
+                value = value
+                    .as_hash()?
+                    .get(&Yaml::String(key.to_string()))?
+                    .clone();
+            }
+            Some(value)
+        }
+    }
+}
+
+/// Text of a scalar to compare it with a string, `None` for lists and mappings
+fn scalar_text(value: &Yaml) -> Option<String> {
+    match value {
+        Yaml::String(value) | Yaml::Real(value) => Some(value.clone()),
+        Yaml::Integer(value) => Some(value.to_string()),
+        Yaml::Boolean(value) => Some(value.to_string()),
+        Yaml::Null => Some("null".to_string()),
+        _ => None,
+    }
+}
+
+/// Compares numbers by value and anything else by its text, `1 == "1"` holds
+fn compare(value: &Yaml, other: &Yaml) -> Option<Ordering> {
+    let number = |value: &Yaml| match value {
+        Yaml::Integer(value) => Some(*value as f64),
+        Yaml::Real(_) => value.as_f64(),
+        _ => None,
+    };
+    match (number(value), number(other)) {
+        (Some(value), Some(other)) => value.partial_cmp(&other),
+        _ => Some(scalar_text(value)?.cmp(&scalar_text(other)?)),
+    }
+}
+
+/// The value itself or each item of a list, so `tags == "a"` finds `tags: [a, b]`
+fn values(value: Yaml) -> Vec<Yaml> {
+    match value {
+        Yaml::Array(values) => values,
+        value => vec![value],
+    }
+}
+
+impl Expression {
+    fn matches(&self, result: &Result) -> bool {
+        match self {
+            Expression::Has(name) => field(result, name).is_some_and(|value| !value.is_null()),
+            Expression::Compare(name, operator, other) => {
+                values(field(result, name).unwrap_or(Yaml::BadValue))
+                    .iter()
+                    .any(|value| {
+                        let ordering = compare(value, other);
+                        match *operator {
+                            "==" => ordering == Some(Ordering::Equal),
+                            "<" => ordering == Some(Ordering::Less),
+                            "<=" => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+                            ">" => ordering == Some(Ordering::Greater),
+                            _ => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+                        }
+                    })
+            }
+            Expression::Matches(name, regex) => {
+                values(field(result, name).unwrap_or(Yaml::BadValue))
+                    .iter()
+                    .filter_map(scalar_text)
+                    .any(|text| regex.is_match(&text))
+            }
+            Expression::Not(expression) => !expression.matches(result),
+            Expression::And(left, right) => left.matches(result) && right.matches(result),
+            Expression::Or(left, right) => left.matches(result) || right.matches(result),
+        }
+    }
+}
+
+/// A filter over results, like `ident == "ID" && (status == "open" || has(tests))`
+///
+/// Fields are compared with `==`, `!=`, `<`, `<=`, `>` and `>=` to quoted strings, numbers,
+/// `true`, `false` or `null`, and matched against regular expressions with `~` and `!~`.
+/// `has(field)` holds if the field is set. Conditions are combined with `!`, `&&`, `||` and
+/// parentheses. Lists hold a condition if one of their items does, missing fields only `!=`.
+#[derive(Clone, Debug)]
+pub struct Query {
+    expression: Expression,
+}
+
+impl FromStr for Query {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Query, String> {
+        let mut parser = Parser {
+            tokens: tokenize(s)?,
+            index: 0,
+            length: s.chars().count(),
+        };
+        let expression = parser.or()?;
+        if parser.peek().is_some() {
+            return Err(format!("unexpected input at {}", parser.position()));
+        }
+        Ok(Query { expression })
+    }
+}
+
+impl Query {
+    /// Checks whether `result` holds the query
+    pub fn matches(&self, result: &Result) -> bool {
+        self.expression.matches(result)
+    }
+}
+
+/// Only the `fields` of `result`, like `{name: A, caption: a}`, missing ones are null
+pub fn select(result: &Result, fields: &[String]) -> Yaml {
+    let mut hash = Hash::new();
+    for name in fields {
+        let value = field(result, name).unwrap_or(Yaml::Null);
+        hash.insert(Yaml::String(name.clone()), value);
+    }
+    Yaml::Hash(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::query::{select, Query};
+    use crate::test_support::curt;
+    use yaml_rust::YamlLoader;
+
+    #[test]
+    fn test_query() {
+        let test_data = "ID[REQ::A, status: open, tests: [T1], prio: 2] ID[REQ::B, status: done, prio: 10]\nREF[REQ::A] ID[IMPL::C, by: {team: x}] #a";
+        let results = curt(&["ID", "REF"], test_data, |_| {});
+        let names = |query: &str| -> Vec<String> {
+            let query: Query = query.parse().unwrap();
+            let mut names: Vec<String> = results
+                .iter()
+                .filter(|result| query.matches(result))
+                .map(|result| format!("{}:{}", result.get_ident(), result.get_name()))
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(
+            names("ident == \"ID\" && status == \"open\""),
+            ["ID:REQ::A"]
+        );
+        assert_eq!(
+            names("name ~ \"^REQ::\" && ident != \"REF\""),
+            ["ID:REQ::A", "ID:REQ::B"]
+        );
+        assert_eq!(
+            names("has(tests) || by.team == \"x\""),
+            ["ID:IMPL::C", "ID:REQ::A"]
+        );
+        assert_eq!(names("prio >= 2 && !(prio > 5)"), ["ID:REQ::A"]);
+        assert_eq!(names("tests == \"T1\" || tag == \"a\"").len(), 2);
+
+        let error = "status = \"open\"".parse::<Query>().err();
+        assert_eq!(
+            error,
+            Some("unknown operator `=` at 8, use `==`".to_string())
+        );
+        let error = "status == open".parse::<Query>().err();
+        assert_eq!(
+            error,
+            Some("expected a value at 11, quote strings like `\"open\"`".to_string())
+        );
+        assert!("(has(tests)".parse::<Query>().is_err());
+        assert!("name ~ \"(\"".parse::<Query>().is_err());
+
+        let fields = vec!["name".to_string(), "status".to_string(), "line".to_string()];
+        let selected = select(&results[0], &fields);
+        let expected = YamlLoader::load_from_str("{name: REQ::A, status: open, line: 1}");
+        assert_eq!(selected, expected.unwrap()[0]);
+    }
+}
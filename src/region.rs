@@ -0,0 +1,228 @@
+use crate::lint::Diagnostic;
+use crate::trace::{parse_annotation, write_annotation};
+use crate::{normalize_line_breaks, IdentRange, Result};
+use yaml_rust::yaml::Hash;
+use yaml_rust::Yaml;
+
+/// Identifiers opening and closing a region like `ID[X, region: true] ... END[X]`
+pub struct RegionConfig {
+    /// Identifiers opening a region with `region: true`, e.g. `ID`
+    pub ids: Vec<String>,
+    /// Identifiers closing the region of a name, e.g. `END`
+    pub ends: Vec<String>,
+}
+
+impl Default for RegionConfig {
+    fn default() -> RegionConfig {
+        RegionConfig {
+            ids: vec!["ID".to_string()],
+            ends: vec!["END".to_string()],
+        }
+    }
+}
+
+/// Line of the char at `offset`, counting `\r\n` as a single line break
+fn line_at(chars: &[char], offset: usize) -> usize {
+    let mut line = 1;
+    for (i, c) in chars.iter().take(offset).enumerate() {
+        if *c == '\n' || (*c == '\r' && chars.get(i + 1) != Some(&'\n')) {
+            line += 1;
+        }
+    }
+    line
+}
+
+fn is_line_break(c: &char) -> bool {
+    *c == '\n' || *c == '\r'
+}
+
+/// Captures the text between the chars `from..to`
+///
+/// If the region spans several lines only the lines in between are captured,
+/// so the lines of the markers like `// ID[X, region: true]` are left out.
+fn capture(chars: &[char], from: usize, to: usize) -> Yaml {
+    let mut from = from.min(chars.len());
+    let mut to = to.clamp(from, chars.len());
+    if chars[from..to].iter().any(is_line_break) {
+        while !is_line_break(&chars[from]) {
+            from += 1;
+        }
+        from += if chars[from..].starts_with(&['\r', '\n']) {
+            2
+        } else {
+            1
+        };
+        while to > from && !is_line_break(&chars[to - 1]) {
+            to -= 1;
+        }
+    } else {
+        while from < to && chars[from].is_whitespace() {
+            from += 1;
+        }
+        while to > from && chars[to - 1].is_whitespace() {
+            to -= 1;
+        }
+    }
+    let raw: String = chars[from..to].iter().collect();
+    let text = normalize_line_breaks(&raw);
+    let text = text.strip_suffix('\n').unwrap_or(&text);
+    let first = line_at(chars, from);
+    let last = first + text.matches('\n').count();
+
+    let mut region = Hash::new();
+    region.insert(
+        Yaml::String("start".to_string()),
+        Yaml::Integer(from as i64),
+    );
+    region.insert(Yaml::String("end".to_string()), Yaml::Integer(to as i64));
+    region.insert(
+        Yaml::String("lines".to_string()),
+        Yaml::Array(vec![
+            Yaml::Integer(first as i64),
+            Yaml::Integer(last as i64),
+        ]),
+    );
+    region.insert(
+        Yaml::String("text".to_string()),
+        Yaml::String(text.to_string()),
+    );
+    Yaml::Hash(region)
+}
+
+/// Captures the text of each region `ID[X, region: true] ... END[X]` as attribute of `X`
+///
+/// `source` is the text the results were extracted from. Regions may nest, each `END[X]`
+/// closes the innermost open region of `X` and is removed from the results.
+/// `region: true` is replaced by the captured `text`, its `lines` and its char offsets.
+pub fn capture_regions(
+    results: Vec<Result>,
+    source: &str,
+    config: &RegionConfig,
+) -> (Vec<Result>, Vec<Diagnostic>) {
+    let chars: Vec<char> = source.chars().collect();
+    let mut ordered: Vec<usize> = (0..results.len())
+        .filter(|index| results[*index].range != IdentRange::Tag)
+        .collect();
+    ordered.sort_by(|a, b| results[*a].position().cmp(&results[*b].position()));
+
+    let mut diagnostics = Vec::new();
+    let mut open: Vec<(usize, String, Yaml)> = Vec::new();
+    let mut regions: Vec<(usize, Yaml)> = Vec::new();
+    let mut closed = vec![false; results.len()];
+    for index in ordered {
+        let result = &results[index];
+        let (name, attributes) = parse_annotation(result);
+        if config.ids.contains(&result.ident) && attributes["region"] == Yaml::Boolean(true) {
+            open.push((index, name, attributes));
+        } else if config.ends.contains(&result.ident) {
+            match open.iter().rposition(|(_, other, _)| *other == name) {
+                Some(position) => {
+                    let (opening, _, mut attributes) = open.remove(position);
+                    let region = capture(&chars, results[opening].end + 1, result.start);
+                    if let Yaml::Hash(hash) = &mut attributes {
+                        if let Some(value) = hash.get_mut(&Yaml::String("region".to_string())) {
+                            *value = region;
+                        }
+                    }
+                    regions.push((opening, attributes));
+                    closed[index] = true;
+                }
+                None => diagnostics.push(
+                    Diagnostic::new(
+                        "unmatched-end",
+                        format!(
+                            "`{}` closes `{}` which is no open region",
+                            result.ident, name
+                        ),
+                        result.line,
+                        result.column,
+                    )
+                    .with_path(result.get_path()),
+                ),
+            }
+        }
+    }
+    for (index, name, _) in open {
+        let result = &results[index];
+        diagnostics.push(
+            Diagnostic::new(
+                "unclosed-region",
+                format!(
+                    "`{}` opens the region `{}` which is never closed",
+                    result.ident, name
+                ),
+                result.line,
+                result.column,
+            )
+            .with_path(result.get_path()),
+        );
+    }
+    diagnostics.sort_by_key(|diagnostic| {
+        let path = diagnostic.get_path().map(|path| path.to_string());
+        (path, diagnostic.get_line(), diagnostic.get_column())
+    });
+
+    let mut output = Vec::new();
+    for (index, mut result) in results.into_iter().enumerate() {
+        if closed[index] {
+            continue;
+        }
+        if let Some((_, attributes)) = regions.iter().find(|(opening, _)| *opening == index) {
+            write_annotation(&mut result, attributes);
+        }
+        output.push(result);
+    }
+    (output, diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::region::RegionConfig;
+    use crate::test_support::{curt, prints, texts};
+
+    fn regions(test_data: &str) -> (Vec<String>, Vec<String>) {
+        let mut diagnostics = Vec::new();
+        let results = curt(&["ID", "END"], test_data, |curt| {
+            diagnostics = curt.capture_regions(test_data, &RegionConfig::default());
+        });
+        (texts(&results), prints(&diagnostics))
+    }
+
+    #[test]
+    fn test_capture_regions() {
+        let test_data = "// ID[OUTER, region: true, caption: c]\nfn a() {}\n  // ID[INNER, region: true]\r\n  fn b() {}\r\n  // END[INNER]\n// END[OUTER]\nID[LINE, region: true] some words END[LINE]";
+        let (results, diagnostics) = regions(test_data);
+        assert_eq!(
+            results,
+            [
+                "{ID: OUTER, region: {start: 39, end: 108, lines: [2, 5], text: \"fn a() {}\\n  // ID[INNER, region: true]\\n  fn b() {}\\n  // END[INNER]\"}, caption: c}",
+                "{ID: INNER, region: {start: 79, end: 92, lines: [4, 4], text: \"  fn b() {}\"}}",
+                "{ID: LINE, region: {start: 145, end: 155, lines: [7, 7], text: some words}}"
+            ]
+        );
+        assert!(diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_region_errors() {
+        let (results, diagnostics) =
+            regions("ID[A, region: true]\nEND[B]\nID[C, region: false]\nEND[C]");
+        assert_eq!(
+            results,
+            [
+                "{ID: A, region: true}",
+                "{END: B}",
+                "{ID: C, region: false}",
+                "{END: C}"
+            ]
+        );
+        assert_eq!(
+            diagnostics,
+            [
+                "<stdin>:1:1: unclosed-region: `ID` opens the region `A` which is never closed",
+                "<stdin>:2:1: unmatched-end: `END` closes `B` which is no open region",
+                "<stdin>:4:1: unmatched-end: `END` closes `C` which is no open region"
+            ]
+        );
+    }
+}
@@ -0,0 +1,256 @@
+use crate::flow::to_flow;
+use crate::lint::Diagnostic;
+use crate::nesting::quote_annotations;
+use crate::trace::parse_annotation;
+use crate::{IdentRange, Result};
+use std::str::FromStr;
+use yaml_rust::{Yaml, YamlLoader};
+
+/// Types an attribute value may have
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Type {
+    String,
+    Integer,
+    /// Integer or real
+    Number,
+    Boolean,
+    List,
+    Map,
+}
+
+impl FromStr for Type {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Type, String> {
+        match s {
+            "string" => Ok(Type::String),
+            "integer" => Ok(Type::Integer),
+            "number" => Ok(Type::Number),
+            "boolean" => Ok(Type::Boolean),
+            "list" => Ok(Type::List),
+            "map" => Ok(Type::Map),
+            _ => Err(format!("unknown type `{}`", s)),
+        }
+    }
+}
+
+impl Type {
+    fn matches(self, value: &Yaml) -> bool {
+        matches!(
+            (self, value),
+            (Type::String, Yaml::String(_))
+                | (Type::Integer, Yaml::Integer(_))
+                | (Type::Number, Yaml::Integer(_))
+                | (Type::Number, Yaml::Real(_))
+                | (Type::Boolean, Yaml::Boolean(_))
+                | (Type::List, Yaml::Array(_))
+                | (Type::Map, Yaml::Hash(_))
+        )
+    }
+}
+
+/// Rules for a single attribute like `status:`
+struct Field {
+    name: String,
+    required: bool,
+    types: Vec<Type>,
+    items: Vec<Type>,
+    values: Vec<Yaml>,
+}
+
+/// Attribute rules per identifier, read from yaml like:
+///
+/// ```yaml
+/// ID:
+///   caption: {type: string, required: true}
+///   status: {enum: [draft, approved, obsolete]}
+///   implements: {type: [string, list], items: string}
+/// ```
+pub struct Schema {
+    rules: Vec<(String, Vec<Field>)>,
+}
+
+/// Reads a single type or a list of alternative types
+fn parse_types(yaml: &Yaml) -> std::result::Result<Vec<Type>, String> {
+    match yaml {
+        Yaml::BadValue => Ok(Vec::new()),
+        Yaml::String(name) => Ok(vec![name.parse()?]),
+        Yaml::Array(names) => {
+            let mut types = Vec::new();
+            for name in names {
+                types.extend(parse_types(name)?);
+            }
+            Ok(types)
+        }
+        other => Err(format!("expected a type, found `{}`", to_flow(other))),
+    }
+}
+
+fn parse_field(name: &str, yaml: &Yaml) -> std::result::Result<Field, String> {
+    let hash = yaml
+        .as_hash()
+        .ok_or_else(|| format!("rules of `{}` are no mapping", name))?;
+    for key in hash.keys() {
+        match key.as_str() {
+            Some("type") | Some("required") | Some("items") | Some("enum") => {}
+            _ => return Err(format!("unknown rule `{}` for `{}`", to_flow(key), name)),
+        }
+    }
+    Ok(Field {
+        name: name.to_string(),
+        required: yaml["required"].as_bool().unwrap_or(false),
+        types: parse_types(&yaml["type"])?,
+        items: parse_types(&yaml["items"])?,
+        values: yaml["enum"].as_vec().cloned().unwrap_or_default(),
+    })
+}
+
+impl FromStr for Schema {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Schema, String> {
+        let docs = YamlLoader::load_from_str(s).map_err(|error| error.to_string())?;
+        let mut rules = Vec::new();
+        if let Some(doc) = docs.first() {
+            let idents = doc.as_hash().ok_or("the schema is no mapping")?;
+            for (ident, fields) in idents {
+                let ident = to_flow(ident);
+                let fields = fields
+                    .as_hash()
+                    .ok_or_else(|| format!("attributes of `{}` are no mapping", ident))?;
+                let fields = fields
+                    .iter()
+                    .map(|(name, field)| parse_field(&to_flow(name), field))
+                    .collect::<std::result::Result<Vec<Field>, String>>()?;
+                rules.push((ident, fields));
+            }
+        }
+        Ok(Schema { rules })
+    }
+}
+
+fn type_names(types: &[Type]) -> String {
+    let names: Vec<String> = types
+        .iter()
+        .map(|kind| format!("{:?}", kind).to_lowercase())
+        .collect();
+    names.join(" or ")
+}
+
+/// Checks a single attribute value against `field`, returns what is wrong with it
+fn check_field(field: &Field, value: &Yaml) -> Option<(&'static str, String)> {
+    let has = format!("`{}: {}`", field.name, to_flow(value));
+    if !field.types.is_empty() && !field.types.iter().any(|kind| kind.matches(value)) {
+        let expected = type_names(&field.types);
+        return Some(("schema-type", format!("{}, expected {}", has, expected)));
+    }
+    if let (false, Yaml::Array(items)) = (field.items.is_empty(), value) {
+        if let Some(item) = items
+            .iter()
+            .find(|item| !field.items.iter().any(|kind| kind.matches(item)))
+        {
+            let expected = type_names(&field.items);
+            let message = format!(
+                "{} with item `{}`, expected {}",
+                has,
+                to_flow(item),
+                expected
+            );
+            return Some(("schema-type", message));
+        }
+    }
+    if !field.values.is_empty() && !field.values.contains(value) {
+        let values: Vec<String> = field.values.iter().map(to_flow).collect();
+        let message = format!("{}, expected one of {}", has, values.join(", "));
+        return Some(("schema-enum", message));
+    }
+    None
+}
+
+impl Schema {
+    /// Reports every annotation not following the rules of its identifier
+    ///
+    /// The attributes are read as written, so `results` must not be nested yet.
+    /// Annotations within them, like `implements: [REF[A]]`, are read as strings.
+    pub fn validate(&self, results: &[Result]) -> Vec<Diagnostic> {
+        let mut diagnostics = Vec::new();
+        for result in quote_annotations(results.to_vec())
+            .iter()
+            .filter(|result| result.range != IdentRange::Tag)
+        {
+            let fields = match self.rules.iter().find(|(ident, _)| *ident == result.ident) {
+                Some((_, fields)) => fields,
+                None => continue,
+            };
+            let (name, attributes) = parse_annotation(result);
+            if name.ends_with("::") {
+                // A namespace like `ID[IMPL::extraction::]` has no attributes
+                continue;
+            }
+            let found = |rule: &str, message: String| {
+                let message = format!("`{}` `{}` {}", result.ident, name, message);
+                Diagnostic::new(rule, message, result.line, result.column)
+                    .with_end(result.end_line, result.end_column)
+                    .with_path(result.get_path())
+            };
+            if attributes.as_hash().is_none() {
+                diagnostics.push(found("schema-yaml", "is no valid yaml mapping".to_string()));
+                continue;
+            }
+            for field in fields {
+                match &attributes[field.name.as_str()] {
+                    Yaml::BadValue if field.required => {
+                        let message = format!("misses the required attribute `{}`", field.name);
+                        diagnostics.push(found("schema-required", message));
+                    }
+                    Yaml::BadValue => {}
+                    value => {
+                        if let Some((rule, message)) = check_field(field, value) {
+                            diagnostics.push(found(rule, format!("has {}", message)));
+                        }
+                    }
+                }
+            }
+        }
+        diagnostics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::schema::Schema;
+    use crate::test_support::{curt, prints};
+
+    const SCHEMA: &str = "ID:\n  caption: {type: string, required: true}\n  status: {enum: [draft, approved, obsolete]}\n  implements:\n    type: [string, list]\n    items: string\n";
+
+    #[test]
+    fn test_schema_parse() {
+        assert!(SCHEMA.parse::<Schema>().is_ok());
+        let error = "ID:\n  caption: {type: text}".parse::<Schema>().err();
+        assert_eq!(error, Some("unknown type `text`".to_string()));
+        let error = "ID:\n  caption: {requird: true}".parse::<Schema>().err();
+        assert_eq!(
+            error,
+            Some("unknown rule `requird` for `caption`".to_string())
+        );
+    }
+
+    #[test]
+    fn test_schema_validate() {
+        let test_data = "ID[REQ::A, caption: a, status: draft, implements: [REQ::B]]\nID[REQ::B, caption: 3, status: done]\nID[REQ::C, caption: c, implements: [REQ::A, 4]] REF[REQ::D]\nID[REQ::E, caption: c, implements: {a: b}]\nID[REQ::F]\nID[REQ::G, a: b: c]\nID[REQ::H, caption: h, implements: [REF[REQ::A]]]\nID[REQ::I, caption: i,\n  implements: [REF[REQ::A], 5]]";
+        let results = curt(&["ID", "REF"], test_data, |_| {});
+        let schema: Schema = SCHEMA.parse().unwrap();
+        assert_eq!(
+            prints(&schema.validate(&results)),
+            [
+                "<stdin>:2:1-2:37: schema-type: `ID` `REQ::B` has `caption: 3`, expected string",
+                "<stdin>:2:1-2:37: schema-enum: `ID` `REQ::B` has `status: done`, expected one of draft, approved, obsolete",
+                "<stdin>:3:1-3:48: schema-type: `ID` `REQ::C` has `implements: [REQ::A, 4]` with item `4`, expected string",
+                "<stdin>:4:1-4:43: schema-type: `ID` `REQ::E` has `implements: {a: b}`, expected string or list",
+                "<stdin>:5:1-5:11: schema-required: `ID` `REQ::F` misses the required attribute `caption`",
+                "<stdin>:6:1-6:20: schema-yaml: `ID` `REQ::G` is no valid yaml mapping",
+                "<stdin>:8:1-9:32: schema-type: `ID` `REQ::I` has `implements: [\"REF[REQ::A]\", 5]` with item `5`, expected string"
+            ]
+        );
+    }
+}
@@ -0,0 +1,194 @@
+use crate::flow::to_flow;
+use crate::lint::Diagnostic;
+use crate::trace::TraceGraph;
+use std::str::FromStr;
+use yaml_rust::{Yaml, YamlLoader};
+
+/// Fingerprints of the nodes each link was last reviewed against, read from yaml like:
+///
+/// ```yaml
+/// IMPL::A:
+///   REQ::A: "27110d715600433c"
+/// ```
+#[derive(Default)]
+pub struct Baseline {
+    reviews: Vec<(String, String, String)>,
+}
+
+impl FromStr for Baseline {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Baseline, String> {
+        let docs = YamlLoader::load_from_str(s).map_err(|error| error.to_string())?;
+        let mut reviews = Vec::new();
+        if let Some(doc) = docs.first().filter(|doc| !doc.is_null()) {
+            let froms = doc.as_hash().ok_or("the baseline is no mapping")?;
+            for (from, tos) in froms {
+                let from = to_flow(from);
+                let tos = tos
+                    .as_hash()
+                    .ok_or_else(|| format!("reviews of `{}` are no mapping", from))?;
+                for (to, fingerprint) in tos {
+                    let fingerprint = fingerprint.as_str().ok_or_else(|| {
+                        format!(
+                            "fingerprint of `{}` in `{}` is no string",
+                            to_flow(to),
+                            from
+                        )
+                    })?;
+                    reviews.push((from.clone(), to_flow(to), fingerprint.to_string()));
+                }
+            }
+        }
+        Ok(Baseline { reviews })
+    }
+}
+
+impl Baseline {
+    /// Marks all links of `graph` as reviewed against the current fingerprints
+    ///
+    /// Reviews of links not within `graph`, e.g. of files not extracted this time, are kept.
+    pub fn review(&mut self, graph: &TraceGraph) {
+        for link in graph.get_links() {
+            let from = match link.get_from() {
+                Some(from) => from.to_string(),
+                None => continue,
+            };
+            let node = match graph.get_node(link.get_to()) {
+                Some(node) => node,
+                None => continue,
+            };
+            let to = link.get_to().to_string();
+            match self
+                .reviews
+                .iter_mut()
+                .find(|review| review.0 == from && review.1 == to)
+            {
+                Some(review) => review.2 = node.get_fingerprint(),
+                None => {
+                    // Kept next to the other reviews of `from` to write them as one mapping
+                    let at = self
+                        .reviews
+                        .iter()
+                        .rposition(|review| review.0 == from)
+                        .map_or(self.reviews.len(), |at| at + 1);
+                    self.reviews.insert(at, (from, to, node.get_fingerprint()));
+                }
+            }
+        }
+    }
+
+    /// return the fingerprint `to` had when the link from `from` was reviewed
+    pub fn get_fingerprint(&self, from: &str, to: &str) -> Option<&str> {
+        self.reviews
+            .iter()
+            .find(|review| review.0 == from && review.1 == to)
+            .map(|review| review.2.as_str())
+    }
+
+    /// return the baseline as yaml, which can be read again via `parse`
+    pub fn get_print(&self) -> String {
+        let mut print = String::new();
+        let mut last: Option<&str> = None;
+        for (from, to, fingerprint) in &self.reviews {
+            if last != Some(from) {
+                print.push_str(&format!("{}:\n", to_flow(&Yaml::String(from.clone()))));
+                last = Some(from);
+            }
+            let to = to_flow(&Yaml::String(to.clone()));
+            print.push_str(&format!("  {}: \"{}\"\n", to, fingerprint));
+        }
+        print
+    }
+}
+
+/// Reports every link whose target changed since it was reviewed in `baseline`
+pub fn suspect_links(graph: &TraceGraph, baseline: &Baseline) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for link in graph.get_links() {
+        let (from, node) = match (link.get_from(), graph.get_node(link.get_to())) {
+            (Some(from), Some(node)) => (from, node),
+            _ => continue,
+        };
+        let reason = match baseline.get_fingerprint(from, link.get_to()) {
+            Some(fingerprint) if fingerprint == node.get_fingerprint() => continue,
+            Some(_) => "changed since it was reviewed",
+            None => "has never been reviewed",
+        };
+        diagnostics.push(
+            Diagnostic::new(
+                "suspect-link",
+                format!(
+                    "`{}` links `{}` to `{}` which {}",
+                    link.get_kind(),
+                    from,
+                    link.get_to(),
+                    reason
+                ),
+                link.get_line(),
+                link.get_column(),
+            )
+            .with_path(link.get_path()),
+        );
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fingerprint::fingerprint;
+    use crate::region::RegionConfig;
+    use crate::suspect::{suspect_links, Baseline};
+    use crate::test_support::{curt, prints};
+    use crate::trace::{TraceConfig, TraceGraph};
+    use yaml_rust::YamlLoader;
+
+    fn trace(test_data: &str) -> TraceGraph {
+        let results = curt(&["ID", "REF", "END"], test_data, |curt| {
+            curt.capture_regions(test_data, &RegionConfig::default());
+        });
+        TraceGraph::new(&results, &TraceConfig::default())
+    }
+
+    #[test]
+    fn test_suspect_links() {
+        let before = "ID[REQ::A, region: true]\nold text\nEND[REQ::A]\nID[REQ::B]\nID[IMPL::A, implements: [REQ::A, REQ::B]]";
+        let mut baseline = Baseline::default();
+        baseline.review(&trace(before));
+        let baseline: Baseline = baseline.get_print().parse().unwrap();
+        assert!(suspect_links(&trace(before), &baseline).is_empty());
+        let moved = format!("\n\n{}", before);
+        assert!(suspect_links(&trace(&moved), &baseline).is_empty());
+
+        let after = "ID[REQ::A, region: true]\nnew text\nEND[REQ::A]\nID[REQ::B]\nID[IMPL::A, implements: [REQ::A, REQ::B]]\nID[TEST::A, tests: REQ::B]";
+        assert_eq!(
+            prints(&suspect_links(&trace(after), &baseline)),
+            [
+                "<stdin>:5:1: suspect-link: `implements` links `IMPL::A` to `REQ::A` which changed since it was reviewed",
+                "<stdin>:6:1: suspect-link: `tests` links `TEST::A` to `REQ::B` which has never been reviewed"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_review_keeps_baseline() {
+        let mut baseline: Baseline =
+            "IMPL::A:\n  REQ::A: \"1\"\n  REQ::B: \"2\"\nTEST::A:\n  REQ::A: \"3\"\n"
+                .parse()
+                .unwrap();
+        baseline.review(&trace(
+            "ID[REQ::A]\nID[REQ::C]\nID[IMPL::A, implements: [REQ::A, REQ::C]]",
+        ));
+        let reviewed = fingerprint(&YamlLoader::load_from_str("{ID: REQ::A}").unwrap()[0]);
+        assert_eq!(
+            baseline.get_fingerprint("IMPL::A", "REQ::A"),
+            Some(reviewed.as_str())
+        );
+        assert_eq!(baseline.get_fingerprint("IMPL::A", "REQ::B"), Some("2"));
+        assert_eq!(baseline.get_fingerprint("TEST::A", "REQ::A"), Some("3"));
+        assert!(baseline.get_fingerprint("IMPL::A", "REQ::C").is_some());
+        let print = baseline.get_print();
+        assert_eq!(print.matches("IMPL::A:").count(), 1);
+        assert!(print.parse::<Baseline>().is_ok());
+    }
+}
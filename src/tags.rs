@@ -0,0 +1,377 @@
+use crate::flow::flow_value;
+use crate::{IdentRange, Payload, Result};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Ways to write a `IdentRange::Tag` as yaml
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TagStyle {
+    /// `#Test` becomes `{#Test}` and `#key: value` becomes `{#key:  value}`
+    Raw,
+    /// `#Test` becomes `{tag: Test}` and `#key: value` becomes `{key: value}`
+    Named,
+    /// `#Test` becomes `{Test: true}` and `#key: value` becomes `{key: value}`
+    Flag,
+}
+
+impl FromStr for TagStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<TagStyle, String> {
+        match s {
+            "raw" => Ok(TagStyle::Raw),
+            "named" => Ok(TagStyle::Named),
+            "flag" => Ok(TagStyle::Flag),
+            _ => Err(format!("unknown tag style `{}`", s)),
+        }
+    }
+}
+
+/// Scopes in which tags are collected into a single `tags: [...]` list
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum TagScope {
+    /// All tags in one line
+    Line,
+    /// All tags between two blank lines
+    Paragraph,
+    /// All tags within the innermost annotation like `ID[...]`, otherwise all tags in one line
+    Annotation,
+}
+
+impl FromStr for TagScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<TagScope, String> {
+        match s {
+            "line" => Ok(TagScope::Line),
+            "paragraph" => Ok(TagScope::Paragraph),
+            "annotation" => Ok(TagScope::Annotation),
+            _ => Err(format!("unknown tag scope `{}`", s)),
+        }
+    }
+}
+
+/// Folds a tag value spread over several lines into one, like yaml folds plain scalars
+pub(crate) fn fold_lines(raw: &str) -> String {
+    if !raw.contains('\n') {
+        return raw.to_string();
+    }
+    let lines: Vec<&str> = raw
+        .trim_end()
+        .split('\n')
+        .map(|line| line.trim_end().trim_end_matches('\\').trim())
+        .collect();
+    lines.join(" ")
+}
+
+/// Splits a tag payload like `key: value` into its key and value
+fn split_payload(payload: &str) -> (&str, Option<&str>) {
+    match payload.find(':') {
+        Some(index) => (&payload[..index], Some(payload[index + 1..].trim())),
+        None => (payload, None),
+    }
+}
+
+/// Writes a single tag as a flow mapping according to `style`
+pub(crate) fn format_tag(raw: &str, payload: &str, style: TagStyle) -> String {
+    match (style, split_payload(payload)) {
+        (TagStyle::Raw, _) => format!("{{{}}}", raw.replacen(':', ": ", 1)),
+        (_, (key, Some(value))) => format!("{{{}: {}}}", flow_value(key), flow_value(value)),
+        (TagStyle::Named, (name, None)) => format!("{{tag: {}}}", flow_value(name)),
+        (TagStyle::Flag, (name, None)) => format!("{{{}: true}}", flow_value(name)),
+    }
+}
+
+/// Writes a single tag as an entry of a `tags: [...]` list
+fn format_item(result: &Result) -> String {
+    match split_payload(&result.payload) {
+        (key, Some(value)) => format!("{{{}: {}}}", flow_value(key), flow_value(value)),
+        (name, None) => flow_value(name),
+    }
+}
+
+/// Removes the chars `from..to` of `chars` together with the separating `,` around them
+fn remove_span(chars: &mut Vec<char>, from: usize, to: usize) {
+    let mut from = from;
+    let mut to = to;
+    let mut before = from;
+    while before > 0 && chars[before - 1].is_whitespace() {
+        before -= 1;
+    }
+    if before > 0 && chars[before - 1] == ',' {
+        from = before - 1;
+    } else {
+        let mut after = to;
+        while after < chars.len() && chars[after].is_whitespace() {
+            after += 1;
+        }
+        if after < chars.len() && chars[after] == ',' {
+            after += 1;
+            while after < chars.len() && chars[after].is_whitespace() {
+                after += 1;
+            }
+            to = after;
+        } else {
+            from = before;
+        }
+    }
+    chars.drain(from..to);
+}
+
+/// Moves `tags` into the annotation `result`, removing them from its payload
+fn merge_into_annotation(result: &mut Result, tags: &[&Result]) {
+    let payload_start = result.start + result.ident.chars().count() + 1;
+    let mut chars: Vec<char> = result.payload.chars().collect();
+    for tag in tags.iter().rev() {
+        let to = (tag.end - payload_start).min(chars.len());
+        let from = (tag.start - payload_start).min(to);
+        remove_span(&mut chars, from, to);
+    }
+    let items: Vec<String> = tags.iter().map(|tag| format_item(tag)).collect();
+    let mut payload: String = chars.into_iter().collect();
+    payload.push_str(&format!(", tags: [{}]", items.join(", ")));
+    result.set_payload(payload);
+}
+
+/// Creates a single `{tags: [...]}` result out of `tags`
+fn create_tag_list(tags: &[&Result]) -> Result {
+    let first = &tags[0];
+    let items: Vec<String> = tags.iter().map(|tag| format_item(tag)).collect();
+    let payload = format!("[{}]", items.join(", "));
+    Result {
+        text: format!("{{tags: {}}}", payload),
+        ident: "tags".to_string(),
+        payload,
+        range: IdentRange::Tag,
+        start: first.start,
+        end: tags[tags.len() - 1].end,
+        line: first.line,
+        column: first.column,
+        end_line: tags[tags.len() - 1].end_line,
+        end_column: tags[tags.len() - 1].end_column,
+        paragraph: first.paragraph,
+        path: first.path.clone(),
+        kind: Payload::Yaml,
+    }
+}
+
+/// Checks whether the payload of `annotation` contains `result`
+fn contains(annotation: &Result, result: &Result) -> bool {
+    annotation.path == result.path
+        && annotation.start + annotation.ident.chars().count() < result.start
+        && result.end <= annotation.end
+}
+
+/// Finds the innermost annotation containing each result, annotations of plain words contain none
+///
+/// The results are swept once in order of their position, keeping the annotations still open.
+pub(crate) fn find_annotations(results: &[Result]) -> Vec<Option<usize>> {
+    let mut order: Vec<usize> = (0..results.len()).collect();
+    order.sort_by(|a, b| {
+        let (a, b) = (&results[*a], &results[*b]);
+        (&a.path, a.start, b.end).cmp(&(&b.path, b.start, a.end))
+    });
+    let mut parents = vec![None; results.len()];
+    let mut open: Vec<usize> = Vec::new();
+    for index in order {
+        let result = &results[index];
+        open.retain(|other| {
+            results[*other].path == result.path && results[*other].end >= result.start
+        });
+        parents[index] = open
+            .iter()
+            .rev()
+            .copied()
+            .find(|other| contains(&results[*other], result));
+        if result.range != IdentRange::Tag && result.kind == Payload::Yaml {
+            open.push(index);
+        }
+    }
+    parents
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum Group<'a> {
+    Annotation(usize),
+    Line(Option<&'a str>, usize),
+    Paragraph(Option<&'a str>, usize),
+}
+
+/// Finds the group a tag within `annotation` is aggregated in, `None` for all other results
+fn find_group(result: &Result, annotation: Option<usize>, scope: TagScope) -> Option<Group<'_>> {
+    if result.range != IdentRange::Tag {
+        return None;
+    }
+    let annotation = match scope {
+        TagScope::Annotation => annotation,
+        _ => None,
+    };
+    Some(match (scope, annotation) {
+        (_, Some(index)) => Group::Annotation(index),
+        (TagScope::Paragraph, None) => Group::Paragraph(result.get_path(), result.paragraph),
+        _ => Group::Line(result.get_path(), result.line),
+    })
+}
+
+/// Collects all tags within each `scope` into one `tags: [...]` list
+///
+/// Tags within an annotation are moved into it for `TagScope::Annotation`,
+/// all other groups become a new `{tags: [...]}` result at the position of their first tag.
+pub fn aggregate_tags(results: Vec<Result>, scope: TagScope) -> Vec<Result> {
+    let annotations = find_annotations(&results);
+    let groups: Vec<Option<Group>> = results
+        .iter()
+        .zip(annotations)
+        .map(|(result, annotation)| find_group(result, annotation, scope))
+        .collect();
+    aggregate(&results, &groups)
+}
+
+/// Moves the tags within annotations into them, all other tags stay as they are
+pub(crate) fn move_into_annotations(results: Vec<Result>) -> Vec<Result> {
+    let annotations = find_annotations(&results);
+    let groups: Vec<Option<Group>> = results
+        .iter()
+        .zip(annotations)
+        .map(|(result, annotation)| match annotation {
+            Some(index) if result.range == IdentRange::Tag => Some(Group::Annotation(index)),
+            _ => None,
+        })
+        .collect();
+    aggregate(&results, &groups)
+}
+
+/// Writes each group of `groups` as one result, `None` stays as it is
+fn aggregate(results: &[Result], groups: &[Option<Group>]) -> Vec<Result> {
+    let mut members: HashMap<&Group, Vec<&Result>> = HashMap::new();
+    for (result, group) in results.iter().zip(groups) {
+        if let Some(group) = group {
+            members.entry(group).or_default().push(result);
+        }
+    }
+    let mut aggregated = Vec::new();
+    for (index, result) in results.iter().enumerate() {
+        match &groups[index] {
+            None => {
+                let mut result = result.clone();
+                if let Some(tags) = members.get(&Group::Annotation(index)) {
+                    merge_into_annotation(&mut result, tags);
+                }
+                aggregated.push(result);
+            }
+            Some(Group::Annotation(_)) => {}
+            // The list is written at the first tag of its group
+            Some(group) => {
+                if let Some(tags) = members.remove(group) {
+                    aggregated.push(create_tag_list(&tags));
+                }
+            }
+        }
+    }
+    aggregated
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{curt, curt_indicators, texts};
+    use crate::{IdentRange, Indicators, TagRules, TagScope, TagStyle};
+
+    fn tag_styles(test_data: &str, style: TagStyle) -> Vec<String> {
+        let indicator_lists = vec![Indicators::new(&["#", "@"], IdentRange::Tag)
+            .with_tag_style(style)
+            .with_tag_rules(TagRules {
+                punctuation: true,
+                ..TagRules::default()
+            })];
+        texts(&curt_indicators(&indicator_lists, test_data, |_| {}))
+    }
+
+    #[test]
+    fn test_tag_styles() {
+        let test_data = "#Test @count: 3 @note: a, b\n";
+        let result = tag_styles(test_data, TagStyle::Raw);
+        assert_eq!(result, ["{#Test}", "{@count:  3}", "{@note:  a, b}"]);
+        let result = tag_styles(test_data, TagStyle::Named);
+        assert_eq!(result, ["{tag: Test}", "{count: 3}", "{note: \"a, b\"}"]);
+        let result = tag_styles("#Test\n@note: a, b\n", TagStyle::Flag);
+        assert_eq!(result, ["{Test: true}", "{note: \"a, b\"}"]);
+    }
+
+    #[test]
+    fn test_tag_typed_values() {
+        let result = texts(&curt(&["ID"], "@count: 3\n@done: true\n", |_| {}));
+        let yaml = crate::YamlLoader::load_from_str(&result[0]).unwrap();
+        assert_eq!(yaml[0]["count"].as_i64(), Some(3));
+        let yaml = crate::YamlLoader::load_from_str(&result[1]).unwrap();
+        assert_eq!(yaml[0]["done"].as_bool(), Some(true));
+    }
+
+    #[test]
+    fn test_tag_value_boundary() {
+        let test_data = "@note: see #a for @owner: me\n@x: y\n    #b z\n@mail: dev@example.com #\n";
+        let result = texts(&curt(&["ID"], test_data, |_| {}));
+        assert_eq!(
+            result,
+            [
+                "{note: see}",
+                "{tag: a}",
+                "{owner: me}",
+                "{x: y}",
+                "{tag: b}",
+                "{mail: \"dev@example.com #\"}"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_tag_multi_line() {
+        let test_data = "  @description: a long\n    wrapped value\n      over lines\n  #next\n@note: joined \\\nhere\n@other: don't\nstop\n";
+        let result = texts(&curt(&["ID"], test_data, |_| {}));
+        assert_eq!(
+            result,
+            [
+                "{description: a long wrapped value over lines}",
+                "{tag: next}",
+                "{note: joined here}",
+                "{other: don't}"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_aggregate_line() {
+        let test_data = "text #a #b\n#c @d: 4\n\n#e\n";
+        let result = curt(&["ID"], test_data, |curt| {
+            curt.aggregate_tags(TagScope::Line)
+        });
+        assert_eq!(
+            texts(&result),
+            ["{tags: [a, b]}", "{tags: [c, {d: 4}]}", "{tags: [e]}"]
+        );
+    }
+
+    #[test]
+    fn test_aggregate_paragraph() {
+        let test_data = "text #a #b\n#c @d: 4\n\n#e\n";
+        let result = curt(&["ID"], test_data, |curt| {
+            curt.aggregate_tags(TagScope::Paragraph)
+        });
+        assert_eq!(texts(&result), ["{tags: [a, b, c, {d: 4}]}", "{tags: [e]}"]);
+    }
+
+    #[test]
+    fn test_aggregate_annotation() {
+        let test_data = "#a ID[X, #b, caption: c #d] #e\nID[#f, Y]\n";
+        let result = curt(&["ID"], test_data, |curt| {
+            curt.aggregate_tags(TagScope::Annotation)
+        });
+        assert_eq!(
+            texts(&result),
+            [
+                "{tags: [a, e]}",
+                "{ID: X, caption: c, tags: [b, d]}",
+                "{ID: Y, tags: [f]}"
+            ]
+        );
+    }
+}
@@ -0,0 +1,551 @@
+use crate::fingerprint::fingerprint;
+use crate::flow::{flow_entry, flow_value, to_flow};
+use crate::{IdentRange, Result};
+use yaml_rust::{Yaml, YamlLoader};
+
+// ID[IMPL::trace_graph, implements: REQ::Traceability]
+/// Identifiers and attributes building the trace graph
+pub struct TraceConfig {
+    /// Identifiers declaring a node, e.g. `ID`
+    pub ids: Vec<String>,
+    /// Identifiers linking the last declared node to another one, e.g. `REF`
+    pub refs: Vec<String>,
+    /// Attributes of a declaration linking it to other nodes, e.g. `implements`
+    pub links: Vec<String>,
+}
+
+impl Default for TraceConfig {
+    fn default() -> TraceConfig {
+        TraceConfig {
+            ids: vec!["ID".to_string()],
+            refs: vec!["REF".to_string()],
+            links: vec!["implements".to_string(), "tests".to_string()],
+        }
+    }
+}
+
+/// Declaration of an ID like `ID[NAME, attribute: value]`
+pub struct Node {
+    name: String,
+    ident: String,
+    attributes: Yaml,
+    path: Option<String>,
+    line: usize,
+    column: usize,
+}
+
+impl Node {
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_ident(&self) -> &str {
+        &self.ident
+    }
+
+    /// return all attributes including the identifier as yaml hash
+    pub fn get_attributes(&self) -> &Yaml {
+        &self.attributes
+    }
+
+    pub fn get_path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    pub fn get_line(&self) -> usize {
+        self.line
+    }
+
+    pub fn get_column(&self) -> usize {
+        self.column
+    }
+
+    /// return the fingerprint of the attributes, see `fingerprint::fingerprint`
+    pub fn get_fingerprint(&self) -> String {
+        fingerprint(&self.attributes)
+    }
+}
+
+/// Link from a node to the node it references
+pub struct Link {
+    from: Option<String>,
+    to: String,
+    kind: String,
+    path: Option<String>,
+    line: usize,
+    column: usize,
+}
+
+impl Link {
+    /// return the referencing node, `None` for a `REF` before any declaration
+    pub fn get_from(&self) -> Option<&str> {
+        self.from.as_deref()
+    }
+
+    pub fn get_to(&self) -> &str {
+        &self.to
+    }
+
+    /// return the attribute or identifier the link was defined by, e.g. `implements` or `REF`
+    pub fn get_kind(&self) -> &str {
+        &self.kind
+    }
+
+    pub fn get_path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    pub fn get_line(&self) -> usize {
+        self.line
+    }
+
+    pub fn get_column(&self) -> usize {
+        self.column
+    }
+}
+
+/// Graph of IDs linked via references and link attributes
+pub struct TraceGraph {
+    nodes: Vec<Node>,
+    links: Vec<Link>,
+}
+
+/// Reads the name and attributes of an annotation, falling back to the first entry if it is no valid yaml
+pub(crate) fn parse_annotation(result: &Result) -> (String, Yaml) {
+    if let Ok(mut docs) = YamlLoader::load_from_str(result.get_text()) {
+        if docs.len() == 1 {
+            let attributes = docs.remove(0);
+            let name = match &attributes[result.get_ident()] {
+                Yaml::String(name) => Some(name.clone()),
+                Yaml::Integer(name) => Some(name.to_string()),
+                Yaml::Real(name) => Some(name.clone()),
+                _ => None,
+            };
+            if let Some(name) = name {
+                return (name, attributes);
+            }
+        }
+    }
+    let name = result.get_payload().split(',').next().unwrap_or("");
+    (name.trim().to_string(), Yaml::Null)
+}
+
+/// Writes `attributes` back into the annotation `result`, the inverse of `parse_annotation`
+pub(crate) fn write_annotation(result: &mut Result, attributes: &Yaml) {
+    if let Yaml::Hash(hash) = attributes {
+        let ident = Yaml::String(result.ident.clone());
+        let mut payload = vec![to_flow(&attributes[result.ident.as_str()])];
+        for (key, value) in hash.iter().filter(|(key, _)| **key != ident) {
+            payload.push(flow_entry(key, value));
+        }
+        result.set_payload(payload.join(", "));
+    }
+}
+
+/// Lists the names a link attribute points to, it may be a single name or a list of names
+fn link_targets(value: &Yaml) -> Vec<String> {
+    match value {
+        Yaml::String(name) => vec![name.clone()],
+        Yaml::Integer(name) => vec![name.to_string()],
+        Yaml::Array(values) => values.iter().flat_map(link_targets).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Lists the identifiers and names of nested references like `{REF: B}` within `value`
+fn nested_targets(value: &Yaml, refs: &[String]) -> Vec<(String, String)> {
+    match value {
+        Yaml::Hash(hash) => {
+            if let (1, Some((Yaml::String(ident), name))) = (hash.len(), hash.front()) {
+                if refs.contains(ident) {
+                    return link_targets(name)
+                        .into_iter()
+                        .map(|name| (ident.clone(), name))
+                        .collect();
+                }
+            }
+            hash.iter()
+                .flat_map(|(key, value)| {
+                    let mut targets = nested_targets(key, refs);
+                    targets.extend(nested_targets(value, refs));
+                    targets
+                })
+                .collect()
+        }
+        Yaml::Array(values) => values
+            .iter()
+            .flat_map(|value| nested_targets(value, refs))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+impl TraceGraph {
+    /// Build the graph from extracted results, which are read in order of their position
+    pub fn new(results: &[Result], config: &TraceConfig) -> TraceGraph {
+        let mut ordered: Vec<&Result> = results
+            .iter()
+            .filter(|result| result.get_range() != IdentRange::Tag)
+            .collect();
+        ordered.sort_by(|a, b| a.position().cmp(&b.position()));
+
+        let mut nodes = Vec::new();
+        let mut links = Vec::new();
+        let mut current: Option<String> = None;
+        for (index, result) in ordered.iter().enumerate() {
+            if index > 0 && ordered[index - 1].path != result.path {
+                // A `REF` at the start of a file does not belong to the last ID of another one
+                current = None;
+            }
+            let ident = result.get_ident().to_string();
+            if config.ids.contains(&ident) {
+                let (name, attributes) = parse_annotation(result);
+                if name.ends_with("::") {
+                    // A namespace like `ID[IMPL::extraction::]` declares no node,
+                    // so a following `REF` does not belong to the ID before it either
+                    current = None;
+                    continue;
+                }
+                for link in &config.links {
+                    for to in link_targets(&attributes[link.as_str()]) {
+                        links.push(Link {
+                            from: Some(name.clone()),
+                            to,
+                            kind: link.clone(),
+                            path: result.path.clone(),
+                            line: result.get_line(),
+                            column: result.get_column(),
+                        });
+                    }
+                }
+                if let Yaml::Hash(hash) = &attributes {
+                    // Nested references like `ID[A, depends: REF[B]]` link by their attribute
+                    for (key, value) in hash.iter().skip(1) {
+                        let mut targets = nested_targets(key, &config.refs);
+                        targets.extend(nested_targets(value, &config.refs));
+                        for (kind, to) in targets {
+                            links.push(Link {
+                                from: Some(name.clone()),
+                                to,
+                                kind: key.as_str().map_or(kind, |key| key.to_string()),
+                                path: result.path.clone(),
+                                line: result.get_line(),
+                                column: result.get_column(),
+                            });
+                        }
+                    }
+                }
+                current = Some(name.clone());
+                nodes.push(Node {
+                    name,
+                    ident,
+                    attributes,
+                    path: result.path.clone(),
+                    line: result.get_line(),
+                    column: result.get_column(),
+                });
+            } else if config.refs.contains(&ident) {
+                let (to, _) = parse_annotation(result);
+                links.push(Link {
+                    from: current.clone(),
+                    to,
+                    kind: ident,
+                    path: result.path.clone(),
+                    line: result.get_line(),
+                    column: result.get_column(),
+                });
+            }
+        }
+        TraceGraph { nodes, links }
+    }
+
+    pub fn get_nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    pub fn get_links(&self) -> &[Link] {
+        &self.links
+    }
+
+    /// return the first declaration of `name`
+    pub fn get_node(&self, name: &str) -> Option<&Node> {
+        self.nodes.iter().find(|node| node.name == name)
+    }
+
+    /// return the nodes `name` links to, e.g. the requirement an implementation implements
+    pub fn parents(&self, name: &str) -> Vec<&str> {
+        let mut parents = Vec::new();
+        for link in &self.links {
+            if link.get_from() == Some(name) && !parents.contains(&link.get_to()) {
+                parents.push(link.get_to());
+            }
+        }
+        parents
+    }
+
+    /// return the nodes linking to `name`, e.g. the implementations of a requirement
+    pub fn children(&self, name: &str) -> Vec<&str> {
+        let mut children = Vec::new();
+        for link in &self.links {
+            if let Some(from) = link.get_from() {
+                if link.to == name && !children.contains(&from) {
+                    children.push(from);
+                }
+            }
+        }
+        children
+    }
+
+    /// return all nodes reachable via `parents`, without `name` itself
+    pub fn ancestors(&self, name: &str) -> Vec<&str> {
+        self.closure(name, |graph, name| graph.parents(name))
+    }
+
+    /// return all nodes reachable via `children`, without `name` itself
+    pub fn descendants(&self, name: &str) -> Vec<&str> {
+        self.closure(name, |graph, name| graph.children(name))
+    }
+
+    /// return the graph in the dot language of graphviz, links are labeled by their kind
+    pub fn get_dot(&self) -> String {
+        let quote = |name: &str| format!("\"{}\"", name.replace('\\', "\\\\").replace('"', "\\\""));
+        let mut dot = String::from("digraph trace {\n");
+        let mut written: Vec<&str> = Vec::new();
+        for node in &self.nodes {
+            if !written.contains(&node.name.as_str()) {
+                dot.push_str(&format!("  {};\n", quote(&node.name)));
+                written.push(&node.name);
+            }
+        }
+        for link in &self.links {
+            if let Some(from) = link.get_from() {
+                dot.push_str(&format!(
+                    "  {} -> {} [label={}];\n",
+                    quote(from),
+                    quote(&link.to),
+                    quote(&link.kind)
+                ));
+            }
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn closure<'a, F>(&'a self, name: &str, next: F) -> Vec<&'a str>
+    where
+        F: Fn(&'a TraceGraph, &str) -> Vec<&'a str>,
+    {
+        let mut found: Vec<&str> = Vec::new();
+        let mut open = next(self, name);
+        while let Some(candidate) = open.pop() {
+            if candidate != name && !found.contains(&candidate) {
+                found.push(candidate);
+                open.extend(next(self, candidate));
+            }
+        }
+        found
+    }
+}
+
+/// Requirements and the link kinds covering them
+pub struct CoverageConfig {
+    /// Prefix of the names of requirements, e.g. `REQ::`
+    pub prefix: String,
+    /// Link kind marking a requirement as implemented
+    pub implements: String,
+    /// Link kind marking a requirement as tested
+    pub tests: String,
+}
+
+impl Default for CoverageConfig {
+    fn default() -> CoverageConfig {
+        CoverageConfig {
+            prefix: "REQ::".to_string(),
+            implements: "implements".to_string(),
+            tests: "tests".to_string(),
+        }
+    }
+}
+
+/// Requirements without implementation or test
+pub struct Coverage {
+    requirements: Vec<String>,
+    unimplemented: Vec<String>,
+    untested: Vec<String>,
+}
+
+/// Percentage of `part` in `total`, an empty total is fully covered
+fn percentage(part: usize, total: usize) -> f64 {
+    if total == 0 {
+        100.0
+    } else {
+        (part as f64) * 100.0 / (total as f64)
+    }
+}
+
+impl Coverage {
+    pub fn get_requirements(&self) -> &[String] {
+        &self.requirements
+    }
+
+    /// return requirements no link of kind `implements` points at
+    pub fn get_unimplemented(&self) -> &[String] {
+        &self.unimplemented
+    }
+
+    /// return requirements no link of kind `tests` points at
+    pub fn get_untested(&self) -> &[String] {
+        &self.untested
+    }
+
+    pub fn get_implemented_percentage(&self) -> f64 {
+        let implemented = self.requirements.len() - self.unimplemented.len();
+        percentage(implemented, self.requirements.len())
+    }
+
+    pub fn get_tested_percentage(&self) -> f64 {
+        let tested = self.requirements.len() - self.untested.len();
+        percentage(tested, self.requirements.len())
+    }
+
+    /// return the coverage as yaml report
+    pub fn get_print(&self) -> String {
+        let list = |names: &[String]| -> String {
+            let items: Vec<String> = names.iter().map(|name| flow_value(name)).collect();
+            format!("[{}]", items.join(", "))
+        };
+        let mut report = String::new();
+        report.push_str(&format!("requirements: {}\n", self.requirements.len()));
+        report.push_str(&format!(
+            "implemented: {:.1}\n",
+            self.get_implemented_percentage()
+        ));
+        report.push_str(&format!("tested: {:.1}\n", self.get_tested_percentage()));
+        report.push_str(&format!("unimplemented: {}\n", list(&self.unimplemented)));
+        report.push_str(&format!("untested: {}\n", list(&self.untested)));
+        report
+    }
+}
+
+impl TraceGraph {
+    /// Checks which requirements have no direct implementation or test linking to them
+    pub fn coverage(&self, config: &CoverageConfig) -> Coverage {
+        let mut requirements: Vec<String> = Vec::new();
+        for node in &self.nodes {
+            if node.name.starts_with(&config.prefix) && !requirements.contains(&node.name) {
+                requirements.push(node.name.clone());
+            }
+        }
+        let is_linked = |name: &String, kind: &str| {
+            self.links
+                .iter()
+                .any(|link| &link.to == name && link.kind == kind && link.from.is_some())
+        };
+        let unimplemented = requirements
+            .iter()
+            .filter(|name| !is_linked(name, &config.implements))
+            .cloned()
+            .collect();
+        let untested = requirements
+            .iter()
+            .filter(|name| !is_linked(name, &config.tests))
+            .cloned()
+            .collect();
+        Coverage {
+            requirements,
+            unimplemented,
+            untested,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::curt;
+    use crate::trace::{CoverageConfig, TraceConfig, TraceGraph};
+
+    fn trace(test_data: &str) -> TraceGraph {
+        let results = curt(&["ID", "REF"], test_data, |_| {});
+        TraceGraph::new(&results, &TraceConfig::default())
+    }
+
+    #[test]
+    fn test_trace_dot() {
+        let graph =
+            trace("REF[REQ::X]\nID[REQ::A]\nID[IMPL::\"A\", implements: REQ::A]\nID[REQ::A]");
+        assert_eq!(
+            graph.get_dot(),
+            "digraph trace {\n  \"REQ::A\";\n  \"IMPL::\\\"A\\\"\";\n  \"IMPL::\\\"A\\\"\" -> \"REQ::A\" [label=\"implements\"];\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_trace_links() {
+        let graph = trace(
+            "REF[REQ::A]\nID[REQ::A, caption: A]\nID[IMPL::A, implements: REQ::A]\nREF[REQ::B]\nID[TEST::A, tests: [IMPL::A, REQ::A]]",
+        );
+        assert_eq!(graph.get_nodes().len(), 3);
+        assert_eq!(graph.get_links().len(), 5);
+        assert_eq!(graph.get_links()[0].get_from(), None);
+        assert_eq!(graph.get_links()[2].get_kind(), "REF");
+        assert_eq!(graph.get_links()[2].get_from(), Some("IMPL::A"));
+        assert_eq!(graph.get_links()[2].get_line(), 4);
+        let node = graph.get_node("REQ::A").unwrap();
+        assert_eq!(node.get_attributes()["caption"].as_str(), Some("A"));
+        assert_eq!(graph.parents("IMPL::A"), ["REQ::A", "REQ::B"]);
+        assert_eq!(graph.children("REQ::A"), ["IMPL::A", "TEST::A"]);
+    }
+
+    #[test]
+    fn test_trace_nested() {
+        let test_data = "ID[REQ::B]\nID[REQ::A, depends: REF[REQ::B], implements: [REF[REQ::C]]]";
+        let results = curt(&["ID", "REF"], test_data, |curt| curt.nest_annotations());
+        let graph = TraceGraph::new(&results, &TraceConfig::default());
+        let links: Vec<(&str, &str)> = graph
+            .get_links()
+            .iter()
+            .map(|link| (link.get_kind(), link.get_to()))
+            .collect();
+        assert_eq!(links, [("depends", "REQ::B"), ("implements", "REQ::C")]);
+        assert_eq!(graph.parents("REQ::A"), ["REQ::B", "REQ::C"]);
+    }
+
+    #[test]
+    fn test_trace_closure() {
+        let graph = trace(
+            "ID[REQ::A]\nID[REQ::B, implements: REQ::A]\nID[IMPL::B, implements: REQ::B]\nID[TEST::B, tests: IMPL::B]\nID[LOOP, implements: [LOOP, TEST::B]]",
+        );
+        let mut ancestors = graph.ancestors("TEST::B");
+        ancestors.sort();
+        assert_eq!(ancestors, ["IMPL::B", "REQ::A", "REQ::B"]);
+        let mut descendants = graph.descendants("REQ::B");
+        descendants.sort();
+        assert_eq!(descendants, ["IMPL::B", "LOOP", "TEST::B"]);
+    }
+
+    #[test]
+    fn test_trace_invalid_yaml() {
+        let graph = trace("ID[IMPL::yaml-extraction, note: a: b]");
+        assert_eq!(graph.get_nodes()[0].get_name(), "IMPL::yaml-extraction");
+        let graph = trace("ID[IMPL::yaml-extraction::]");
+        assert!(graph.get_nodes().is_empty());
+        let graph = trace("ID[A] ID[NS::] REF[B]");
+        assert_eq!(graph.get_links()[0].get_from(), None);
+        assert!(graph.parents("A").is_empty());
+    }
+
+    #[test]
+    fn test_coverage() {
+        let graph = trace(
+            "ID[REQ::A]\nID[REQ::B]\nID[REQ::C]\nID[IMPL::A, implements: [REQ::A, REQ::B]]\nID[TEST::A, tests: REQ::A]\nID[OTHER::D]",
+        );
+        let coverage = graph.coverage(&CoverageConfig::default());
+        assert_eq!(coverage.get_requirements(), ["REQ::A", "REQ::B", "REQ::C"]);
+        assert_eq!(coverage.get_unimplemented(), ["REQ::C"]);
+        assert_eq!(coverage.get_untested(), ["REQ::B", "REQ::C"]);
+        assert_eq!(
+            coverage.get_print(),
+            "requirements: 3\nimplemented: 66.7\ntested: 33.3\nunimplemented: [REQ::C]\nuntested: [REQ::B, REQ::C]\n"
+        );
+    }
+}